@@ -0,0 +1,78 @@
+//! Throughput of each reader-writer lock in the crate at 1 to N threads.
+//!
+//! Run with `cargo bench`. Each thread repeatedly takes a read or write lock
+//! around a short critical section for a fixed time, and the total number of
+//! acquisitions per second is reported. The read-only runs show the cost of
+//! the shared reader counter in `RawPFLock` against `RawPFDistLock`.
+
+use lock_api::{RawRwLock, RwLock};
+use pflock::{RawPFDistLock, RawPFLock, RawRPLock, RawTFLock, RawWPLock};
+use rand::Rng;
+use std::any::type_name;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DURATION: Duration = Duration::from_millis(500);
+const WRITE_RATIOS: [f64; 3] = [0.0, 0.01, 0.1];
+
+fn run<R: RawRwLock + Send + Sync + 'static>(num_threads: usize, write_ratio: f64) -> f64 {
+    let lock = Arc::new(RwLock::<R, _>::new(0usize));
+    let stop = Arc::new(AtomicBool::new(false));
+    let barrier = Arc::new(Barrier::new(num_threads + 1));
+
+    let mut handles = vec![];
+    for _ in 0..num_threads {
+        let lock = Arc::clone(&lock);
+        let stop = Arc::clone(&stop);
+        let barrier = Arc::clone(&barrier);
+        handles.push(thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            let mut ops = 0usize;
+            barrier.wait();
+            while !stop.load(Ordering::Relaxed) {
+                if rng.gen_bool(write_ratio) {
+                    *lock.write() += 1;
+                } else {
+                    let _ = *lock.read();
+                }
+                ops += 1;
+            }
+            ops
+        }));
+    }
+
+    barrier.wait();
+    let now = Instant::now();
+    thread::sleep(DURATION);
+    stop.store(true, Ordering::Relaxed);
+
+    let ops: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    ops as f64 / now.elapsed().as_secs_f64()
+}
+
+fn bench<R: RawRwLock + Send + Sync + 'static>(max_threads: usize, write_ratio: f64) {
+    for num_threads in 1..=max_threads {
+        println!(
+            "{},{},{},{:.0}",
+            type_name::<R>(),
+            write_ratio,
+            num_threads,
+            run::<R>(num_threads, write_ratio)
+        );
+    }
+}
+
+fn main() {
+    let max_threads = thread::available_parallelism().map_or(4, |n| n.get());
+
+    println!("lock,write_ratio,threads,ops_per_sec");
+    for &write_ratio in WRITE_RATIOS.iter() {
+        bench::<RawPFLock>(max_threads, write_ratio);
+        bench::<RawPFDistLock>(max_threads, write_ratio);
+        bench::<RawTFLock>(max_threads, write_ratio);
+        bench::<RawRPLock>(max_threads, write_ratio);
+        bench::<RawWPLock>(max_threads, write_ratio);
+    }
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    // Generate the C header from the exported functions, sized for the
+    // `stats` feature if it is on. It goes to OUT_DIR, as a build script must
+    // not write to the source tree.
+    let mut config = cbindgen::Config::from_root_or_default(&crate_dir);
+    if env::var_os("CARGO_FEATURE_STATS").is_some() {
+        let after_includes = config.after_includes.take().unwrap_or_default();
+        config.after_includes = Some(format!("\n#define PFLOCK_STATS{}", after_includes));
+    }
+    let header = cbindgen::generate_with_config(&crate_dir, config)
+        .expect("failed to generate the C header");
+    header.write_to_file(out_dir.join("pflock.h"));
+
+    // The copy in include/ for C users is only rewritten on request, see the
+    // README. `tests/c.rs` checks that it is up to date.
+    if env::var_os("PFLOCK_FFI_UPDATE_HEADER").is_some() {
+        header.write_to_file(crate_dir.join("include/pflock.h"));
+    }
+
+    // Compile the C test against the generated header. It is only linked into
+    // `tests/c.rs`, which names it with `#[link]`, not into the library. Its
+    // warnings only fail the build on request, e.g. in CI, so that a newer
+    // compiler's warnings don't break builds of crates that depend on us.
+    cc::Build::new()
+        .file("tests/c/pflock_test.c")
+        .include(&out_dir)
+        .warnings_into_errors(env::var_os("PFLOCK_FFI_STRICT").is_some())
+        .cargo_metadata(false)
+        .compile("pflock_c_test");
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=tests/c/pflock_test.c");
+    println!("cargo:rerun-if-env-changed=PFLOCK_FFI_UPDATE_HEADER");
+    println!("cargo:rerun-if-env-changed=PFLOCK_FFI_STRICT");
+}
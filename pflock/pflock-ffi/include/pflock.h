@@ -0,0 +1,91 @@
+#ifndef PFLOCK_H
+#define PFLOCK_H
+
+/* Generated from src/lib.rs by cbindgen (see build.rs). Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Static initializer for a `pflock_t`, e.g.
+//
+//     static pflock_t lock = PFLOCK_INITIALIZER;
+#define PFLOCK_INITIALIZER { { 0 } }
+
+#if !defined(PFLOCK_STATS)
+// Size of a `pflock_t` in words: the four counters of the lock.
+#define PFLOCK_WORDS 4
+#endif
+
+#if defined(PFLOCK_STATS)
+// Size of a `pflock_t` in words: the four counters of the lock and, with the
+// `stats` feature, its blocking statistics.
+#define PFLOCK_WORDS 90
+#endif
+
+// A phase-fair reader-writer lock.
+//
+// The contents are private. Initialize a lock with `PFLOCK_INITIALIZER` or
+// allocate one with `pflock_new`, and do not move it while it is in use.
+typedef struct pflock_t {
+  uintptr_t state[PFLOCK_WORDS];
+} pflock_t;
+
+// Allocate a new, unlocked lock. Free it with `pflock_free`.
+struct pflock_t *pflock_new(void);
+
+// Free a lock allocated with `pflock_new`.
+//
+// # Safety
+//
+// `lock` must come from `pflock_new` and must not be locked or used again.
+// A null `lock` is ignored.
+void pflock_free(struct pflock_t *lock);
+
+// Acquire `lock` for reading, spinning while a writer phase is in progress.
+//
+// # Safety
+//
+// `lock` must point to an initialized `pflock_t`.
+void pflock_read_lock(struct pflock_t *lock);
+
+// Try to acquire `lock` for reading without blocking. Returns whether the
+// lock was acquired.
+//
+// # Safety
+//
+// `lock` must point to an initialized `pflock_t`.
+bool pflock_read_trylock(struct pflock_t *lock);
+
+// Release a read lock on `lock`.
+//
+// # Safety
+//
+// The calling thread must hold a read lock on `lock`.
+void pflock_read_unlock(struct pflock_t *lock);
+
+// Acquire `lock` for writing, spinning until it is this writer's turn and the
+// readers of the current phase have left.
+//
+// # Safety
+//
+// `lock` must point to an initialized `pflock_t`.
+void pflock_write_lock(struct pflock_t *lock);
+
+// Try to acquire `lock` for writing without blocking. Returns whether the
+// lock was acquired.
+//
+// # Safety
+//
+// `lock` must point to an initialized `pflock_t`.
+bool pflock_write_trylock(struct pflock_t *lock);
+
+// Release the write lock on `lock`.
+//
+// # Safety
+//
+// The calling thread must hold the write lock on `lock`.
+void pflock_write_unlock(struct pflock_t *lock);
+
+#endif /* PFLOCK_H */
@@ -0,0 +1,150 @@
+//! C bindings for the phase-fair reader-writer lock of [`pflock`].
+//!
+//! The build script generates `pflock.h` from this file with cbindgen, and a
+//! copy for C users is checked in as `include/pflock.h`.
+//! A lock is a `pflock_t`, either allocated with `pflock_new` or declared with
+//! the static initializer `PFLOCK_INITIALIZER`:
+//!
+//! ```c
+//! #include "pflock.h"
+//!
+//! static pflock_t lock = PFLOCK_INITIALIZER;
+//!
+//! void reader(void) {
+//!     pflock_read_lock(&lock);
+//!     /* ... */
+//!     pflock_read_unlock(&lock);
+//! }
+//! ```
+//!
+//! The lock and unlock functions are those of [`RawPFLock`]: they spin instead
+//! of suspending and never allocate, so the phase-fair blocking bounds hold
+//! for C callers too. Build the crate as a `staticlib` or `cdylib` to link it
+//! into a C program.
+//!
+//! The `stats` feature turns on the blocking statistics of pflock, which make
+//! the lock larger; the header generated with it defines `PFLOCK_STATS` and
+//! sizes `pflock_t` to match.
+#![allow(non_camel_case_types)]
+
+use lock_api::RawRwLock;
+use pflock::RawPFLock;
+use std::mem::{align_of, size_of};
+
+/// A phase-fair reader-writer lock.
+///
+/// The contents are private. Initialize a lock with `PFLOCK_INITIALIZER` or
+/// allocate one with `pflock_new`, and do not move it while it is in use.
+#[repr(C)]
+pub struct pflock_t {
+    state: [usize; PFLOCK_WORDS],
+}
+
+/// Size of a `pflock_t` in words: the four counters of the lock.
+#[cfg(not(feature = "stats"))]
+pub const PFLOCK_WORDS: usize = 4;
+
+/// Size of a `pflock_t` in words: the four counters of the lock and, with the
+/// `stats` feature, its blocking statistics.
+#[cfg(feature = "stats")]
+pub const PFLOCK_WORDS: usize = 90;
+
+// A `pflock_t` is a `RawPFLock`, whose all-zero state is unlocked. The `stats`
+// feature of pflock adds to the lock, so it has to be turned on through the
+// `stats` feature of this crate, which also sizes the header for it.
+const _: () = assert!(
+    size_of::<pflock_t>() == size_of::<RawPFLock>()
+        && align_of::<pflock_t>() == align_of::<RawPFLock>(),
+    "pflock's `stats` feature needs the `stats` feature of pflock-ffi, on a 64-bit target"
+);
+
+/// The lock behind `lock`.
+///
+/// # Safety
+///
+/// `lock` must point to an initialized `pflock_t` that outlives `'a`.
+unsafe fn raw<'a>(lock: *mut pflock_t) -> &'a RawPFLock {
+    &*(lock as *const RawPFLock)
+}
+
+/// Allocate a new, unlocked lock. Free it with `pflock_free`.
+#[no_mangle]
+pub extern "C" fn pflock_new() -> *mut pflock_t {
+    Box::into_raw(Box::new(RawPFLock::INIT)) as *mut pflock_t
+}
+
+/// Free a lock allocated with `pflock_new`.
+///
+/// # Safety
+///
+/// `lock` must come from `pflock_new` and must not be locked or used again.
+/// A null `lock` is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_free(lock: *mut pflock_t) {
+    if !lock.is_null() {
+        drop(Box::from_raw(lock as *mut RawPFLock));
+    }
+}
+
+/// Acquire `lock` for reading, spinning while a writer phase is in progress.
+///
+/// # Safety
+///
+/// `lock` must point to an initialized `pflock_t`.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_read_lock(lock: *mut pflock_t) {
+    raw(lock).lock_shared();
+}
+
+/// Try to acquire `lock` for reading without blocking. Returns whether the
+/// lock was acquired.
+///
+/// # Safety
+///
+/// `lock` must point to an initialized `pflock_t`.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_read_trylock(lock: *mut pflock_t) -> bool {
+    raw(lock).try_lock_shared()
+}
+
+/// Release a read lock on `lock`.
+///
+/// # Safety
+///
+/// The calling thread must hold a read lock on `lock`.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_read_unlock(lock: *mut pflock_t) {
+    raw(lock).unlock_shared();
+}
+
+/// Acquire `lock` for writing, spinning until it is this writer's turn and the
+/// readers of the current phase have left.
+///
+/// # Safety
+///
+/// `lock` must point to an initialized `pflock_t`.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_write_lock(lock: *mut pflock_t) {
+    raw(lock).lock_exclusive();
+}
+
+/// Try to acquire `lock` for writing without blocking. Returns whether the
+/// lock was acquired.
+///
+/// # Safety
+///
+/// `lock` must point to an initialized `pflock_t`.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_write_trylock(lock: *mut pflock_t) -> bool {
+    raw(lock).try_lock_exclusive()
+}
+
+/// Release the write lock on `lock`.
+///
+/// # Safety
+///
+/// The calling thread must hold the write lock on `lock`.
+#[no_mangle]
+pub unsafe extern "C" fn pflock_write_unlock(lock: *mut pflock_t) {
+    raw(lock).unlock_exclusive();
+}
@@ -0,0 +1,50 @@
+//! Runs the C tests in `tests/c/pflock_test.c`, which the build script
+//! compiles against the header it generates in `OUT_DIR`.
+
+use lock_api::RawRwLock;
+use pflock::RawPFLock;
+use pflock_ffi::PFLOCK_WORDS;
+use std::mem;
+use std::os::raw::c_int;
+
+#[link(name = "pflock_c_test", kind = "static")]
+extern "C" {
+    fn pflock_c_test_static() -> c_int;
+    fn pflock_c_test_heap() -> c_int;
+    fn pflock_c_test_threads() -> c_int;
+}
+
+#[test]
+fn initializer_matches_init() {
+    // `PFLOCK_INITIALIZER` is all zeroes
+    let init: [usize; PFLOCK_WORDS] = unsafe { mem::transmute(RawPFLock::INIT) };
+    assert_eq!(init, [0; PFLOCK_WORDS]);
+}
+
+/// The header in `include/` is a copy of the generated one, for C users.
+/// Regenerate it with `PFLOCK_FFI_UPDATE_HEADER=1 cargo build -p pflock-ffi`.
+#[test]
+#[cfg(not(feature = "stats"))]
+fn header_is_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/pflock.h"));
+    let copy = include_str!("../include/pflock.h");
+    assert!(
+        generated == copy,
+        "include/pflock.h is stale, regenerate it with PFLOCK_FFI_UPDATE_HEADER=1"
+    );
+}
+
+#[test]
+fn static_lock() {
+    assert_eq!(unsafe { pflock_c_test_static() }, 0);
+}
+
+#[test]
+fn heap_lock() {
+    assert_eq!(unsafe { pflock_c_test_heap() }, 0);
+}
+
+#[test]
+fn threads() {
+    assert_eq!(unsafe { pflock_c_test_threads() }, 0);
+}
@@ -0,0 +1,113 @@
+/*
+ * Tests of the C API, compiled by build.rs and run from tests/c.rs. Each test
+ * returns 0 on success and prints the failed check otherwise.
+ */
+#include <pthread.h>
+#include <stdio.h>
+
+#include "pflock.h"
+
+#define THREADS 2
+#define ITERATIONS 1000
+
+#define CHECK(cond)                                                        \
+    do {                                                                   \
+        if (!(cond)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,         \
+                    __LINE__, #cond);                                      \
+            return 1;                                                      \
+        }                                                                  \
+    } while (0)
+
+static pflock_t static_lock = PFLOCK_INITIALIZER;
+
+/* The try variants on a lock that is free, read-locked and write-locked */
+static int check_try(pflock_t *lock)
+{
+    CHECK(pflock_read_trylock(lock));
+    CHECK(pflock_read_trylock(lock));
+    CHECK(!pflock_write_trylock(lock));
+    /* The failed attempt must not hold up later readers */
+    CHECK(pflock_read_trylock(lock));
+    pflock_read_unlock(lock);
+    pflock_read_unlock(lock);
+    pflock_read_unlock(lock);
+
+    CHECK(pflock_write_trylock(lock));
+    CHECK(!pflock_read_trylock(lock));
+    CHECK(!pflock_write_trylock(lock));
+    pflock_write_unlock(lock);
+
+    pflock_write_lock(lock);
+    pflock_write_unlock(lock);
+    pflock_read_lock(lock);
+    pflock_read_unlock(lock);
+
+    CHECK(pflock_write_trylock(lock));
+    pflock_write_unlock(lock);
+    return 0;
+}
+
+int pflock_c_test_static(void)
+{
+    return check_try(&static_lock);
+}
+
+int pflock_c_test_heap(void)
+{
+    pflock_t *lock = pflock_new();
+    int result;
+
+    CHECK(lock != NULL);
+    result = check_try(lock);
+    pflock_free(lock);
+    return result;
+}
+
+/* Writers increment both counters, readers check that they always match */
+static pflock_t shared_lock = PFLOCK_INITIALIZER;
+static long first, second;
+static int torn_reads;
+
+static void *writer(void *arg)
+{
+    (void)arg;
+    for (int i = 0; i < ITERATIONS; i++) {
+        pflock_write_lock(&shared_lock);
+        first++;
+        second++;
+        pflock_write_unlock(&shared_lock);
+    }
+    return NULL;
+}
+
+static void *reader(void *arg)
+{
+    (void)arg;
+    for (int i = 0; i < ITERATIONS; i++) {
+        pflock_read_lock(&shared_lock);
+        if (first != second) {
+            torn_reads++;
+        }
+        pflock_read_unlock(&shared_lock);
+    }
+    return NULL;
+}
+
+int pflock_c_test_threads(void)
+{
+    pthread_t threads[2 * THREADS];
+
+    for (int i = 0; i < THREADS; i++) {
+        CHECK(pthread_create(&threads[2 * i], NULL, writer, NULL) == 0);
+        CHECK(pthread_create(&threads[2 * i + 1], NULL, reader, NULL) == 0);
+    }
+    for (int i = 0; i < 2 * THREADS; i++) {
+        CHECK(pthread_join(threads[i], NULL) == 0);
+    }
+
+    CHECK(first == (long)THREADS * ITERATIONS);
+    CHECK(second == first);
+    CHECK(torn_reads == 0);
+    return 0;
+}
@@ -0,0 +1,498 @@
+use super::{
+    try_start_phase, try_take_wticket, try_withdraw_reader, AtomicUsize, Ordering, PRES, RINC,
+    WBITS, ZERO_MASK,
+};
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// Tasks waiting for an `AsyncPFLock`, by what they wait for.
+#[derive(Default)]
+struct Waiters {
+    /// Readers waiting for the current writer phase to end
+    readers: Vec<Waker>,
+    /// Writers waiting for their ticket to be served
+    writers: Vec<(usize, Waker)>,
+    /// Tickets of writers that were dropped before their turn
+    abandoned: Vec<usize>,
+    /// The writer waiting for the readers of the previous phase to leave
+    drain: Option<Waker>,
+    /// Reader ticket of a writer phase whose writer was dropped while waiting
+    /// for the readers of the previous phase. The last of them ends it.
+    abandoned_phase: Option<usize>,
+}
+
+/// An async phase-fair reader-writer lock.
+///
+/// `AsyncPFLock` runs the `rin`/`rout`/`win`/`wout` protocol of
+/// [`RawPFLock`](crate::RawPFLock), but a task that has to wait returns
+/// `Pending` and is woken through its `Waker` instead of spinning:
+///
+/// - readers that arrive during a writer phase are all woken together when
+///   that writer releases the lock, so the next reader phase starts at once,
+/// - writers are woken one at a time, in ticket order,
+/// - a writer waiting for the readers of the previous phase is woken by the
+///   last of them.
+///
+/// The lock does not depend on an executor. The wakers are kept behind a
+/// `std::sync::Mutex` that is only taken when a task has to wait or wake
+/// another; uncontended `read` and `write` only touch the counters.
+///
+/// The `read` and `write` futures may be dropped while they wait. A dropped
+/// reader takes back its arrival and a dropped writer gives up its ticket. A
+/// writer that is dropped while waiting for the readers of the previous phase
+/// has already started its own, which cannot be taken back: readers that
+/// arrived since wait until the last reader of the previous phase leaves and
+/// ends the abandoned phase, as if the writer had released the lock at once.
+pub struct AsyncPFLock<T: ?Sized> {
+    rin: AtomicUsize,
+    rout: AtomicUsize,
+    win: AtomicUsize,
+    wout: AtomicUsize,
+    waiters: Mutex<Waiters>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncPFLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AsyncPFLock<T> {}
+
+impl<T> AsyncPFLock<T> {
+    /// A new, unlocked lock around `val`.
+    pub fn new(val: T) -> Self {
+        AsyncPFLock {
+            rin: AtomicUsize::new(0),
+            rout: AtomicUsize::new(0),
+            win: AtomicUsize::new(0),
+            wout: AtomicUsize::new(0),
+            waiters: Mutex::new(Waiters::default()),
+            data: UnsafeCell::new(val),
+        }
+    }
+
+    /// Consume the lock and return the protected value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> AsyncPFLock<T> {
+    /// Acquire the lock for reading. The future waits for at most one writer
+    /// phase.
+    pub fn read(&self) -> AsyncPFLockReadFuture<'_, T> {
+        AsyncPFLockReadFuture {
+            lock: self,
+            state: ReadState::Start,
+        }
+    }
+
+    /// Acquire the lock for writing. The future waits for the writers ahead of
+    /// it and then for the readers of the current phase.
+    pub fn write(&self) -> AsyncPFLockWriteFuture<'_, T> {
+        AsyncPFLockWriteFuture {
+            lock: self,
+            state: WriteState::Start,
+        }
+    }
+
+    /// Acquire the lock for reading if no writer phase is in progress.
+    pub fn try_read(&self) -> Option<AsyncPFLockReadGuard<'_, T>> {
+        let w = self.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+
+        if w & PRES == 0 || !try_withdraw_reader(&self.rin, w) {
+            Some(AsyncPFLockReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Acquire the lock for writing if no other task holds or waits for it.
+    pub fn try_write(&self) -> Option<AsyncPFLockWriteGuard<'_, T>> {
+        // Only take a writer ticket if no writer holds or waits for the lock
+        try_take_wticket(&self.win, &self.wout)?;
+
+        // Set the writer bits only if no reader holds or waits for the lock
+        if !try_start_phase(&self.rin, &self.rout) {
+            let writer = self.release_wticket(&mut self.waiters());
+            if let Some(waker) = writer {
+                waker.wake();
+            }
+            return None;
+        }
+
+        Some(AsyncPFLockWriteGuard { lock: self })
+    }
+
+    /// The protected value. No locking is needed since `&mut self` rules out
+    /// other users.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn waiters(&self) -> MutexGuard<'_, Waiters> {
+        // Wakers are called after the mutex is released, so nothing panics
+        // while it is held
+        self.waiters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether writer phase `w` is over. Registers the task to be woken when
+    /// it ends otherwise.
+    fn phase_over(&self, w: usize, cx: &mut Context<'_>) -> bool {
+        if w != (self.rin.load(Ordering::Acquire) & WBITS) {
+            return true;
+        }
+
+        // The writer clears its bits before taking the mutex to wake us, so
+        // checking again under the mutex cannot miss the wake-up
+        let mut waiters = self.waiters();
+        if w != (self.rin.load(Ordering::Acquire) & WBITS) {
+            return true;
+        }
+        // A task polled again while it waits is registered only once
+        if !waiters
+            .readers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            waiters.readers.push(cx.waker().clone());
+        }
+        false
+    }
+
+    /// Whether it is `wticket`'s turn to write-lock the resource. Registers
+    /// the task to be woken when it is otherwise.
+    fn wticket_served(&self, wticket: usize, cx: &mut Context<'_>) -> bool {
+        if wticket == self.wout.load(Ordering::Acquire) {
+            return true;
+        }
+
+        // wout only changes under the mutex
+        let mut waiters = self.waiters();
+        if wticket == self.wout.load(Ordering::Acquire) {
+            return true;
+        }
+        match waiters.writers.iter_mut().find(|(t, _)| *t == wticket) {
+            Some((_, waker)) => *waker = cx.waker().clone(),
+            None => waiters.writers.push((wticket, cx.waker().clone())),
+        }
+        false
+    }
+
+    /// Whether all readers that arrived before `rticket` have finished.
+    /// Registers the task to be woken by the last of them otherwise.
+    fn readers_done(&self, rticket: usize, cx: &mut Context<'_>) -> bool {
+        if rticket == self.rout.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        let mut waiters = self.waiters();
+        if rticket == self.rout.load(Ordering::SeqCst) {
+            return true;
+        }
+        match &mut waiters.drain {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            drain => *drain = Some(cx.waker().clone()),
+        }
+        false
+    }
+
+    /// Give up the writer phase with reader ticket `rticket` while its readers
+    /// are still draining. The phase cannot be taken back, so it is ended as
+    /// soon as the last of them leaves, by that reader.
+    fn abandon_phase(&self, rticket: usize) {
+        {
+            let mut waiters = self.waiters();
+            waiters.drain = None;
+
+            // Readers check for an abandoned phase under the mutex after
+            // leaving, so either they see it or we see them gone
+            if rticket != self.rout.load(Ordering::SeqCst) {
+                waiters.abandoned_phase = Some(rticket);
+                return;
+            }
+        }
+        self.unlock_exclusive();
+    }
+
+    fn unlock_shared(&self) {
+        // Increment rout to mark the read-lock returned
+        self.rout.fetch_add(RINC, Ordering::SeqCst);
+
+        // If a writer is present it may be waiting for this reader, or have
+        // been dropped and left it to the last reader to end its phase
+        if self.rin.load(Ordering::SeqCst) & PRES != 0 {
+            let (waker, abandoned) = {
+                let mut waiters = self.waiters();
+                let abandoned = match waiters.abandoned_phase {
+                    Some(rticket) if rticket == self.rout.load(Ordering::SeqCst) => {
+                        waiters.abandoned_phase.take()
+                    }
+                    _ => None,
+                };
+                (waiters.drain.take(), abandoned.is_some())
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            if abandoned {
+                self.unlock_exclusive();
+            }
+        }
+    }
+
+    fn unlock_exclusive(&self) {
+        // Clear the writer present bit, then wake every reader of the next
+        // phase and the next writer in line
+        self.rin.fetch_and(!PRES, Ordering::Release);
+
+        let (readers, writer) = {
+            let mut waiters = self.waiters();
+            let readers = std::mem::take(&mut waiters.readers);
+            (readers, self.release_wticket(&mut waiters))
+        };
+        for waker in readers.into_iter().chain(writer) {
+            waker.wake();
+        }
+    }
+
+    /// Hand the writer ticket on to the next writer that is still waiting and
+    /// return its waker, if it has registered one.
+    fn release_wticket(&self, waiters: &mut Waiters) -> Option<Waker> {
+        loop {
+            let next = self.wout.fetch_add(1, Ordering::Release) + 1;
+
+            match waiters.abandoned.iter().position(|t| *t == next) {
+                Some(i) => {
+                    waiters.abandoned.swap_remove(i);
+                }
+                None => {
+                    let i = waiters.writers.iter().position(|(t, _)| *t == next)?;
+                    return Some(waiters.writers.swap_remove(i).1);
+                }
+            }
+        }
+    }
+
+    /// Give up `wticket` before its turn has come.
+    fn abandon_wticket(&self, wticket: usize) {
+        let writer = {
+            let mut waiters = self.waiters();
+            waiters.writers.retain(|(t, _)| *t != wticket);
+
+            if wticket == self.wout.load(Ordering::Acquire) {
+                // Our turn came after all, so pass it on right away
+                self.release_wticket(&mut waiters)
+            } else {
+                waiters.abandoned.push(wticket);
+                None
+            }
+        };
+        if let Some(waker) = writer {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Default> Default for AsyncPFLock<T> {
+    fn default() -> Self {
+        AsyncPFLock::new(T::default())
+    }
+}
+
+impl<T> From<T> for AsyncPFLock<T> {
+    fn from(val: T) -> Self {
+        AsyncPFLock::new(val)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AsyncPFLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f
+                .debug_struct("AsyncPFLock")
+                .field("data", &&*guard)
+                .finish(),
+            None => f
+                .debug_struct("AsyncPFLock")
+                .field("data", &format_args!("<locked>"))
+                .finish(),
+        }
+    }
+}
+
+enum ReadState {
+    Start,
+    /// Counted in rin during writer phase `w`
+    Waiting(usize),
+    Done,
+}
+
+/// The future returned by [`AsyncPFLock::read`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct AsyncPFLockReadFuture<'a, T: ?Sized> {
+    lock: &'a AsyncPFLock<T>,
+    state: ReadState,
+}
+
+impl<'a, T: ?Sized> Future for AsyncPFLockReadFuture<'a, T> {
+    type Output = AsyncPFLockReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.lock;
+
+        let w = match self.state {
+            ReadState::Start => {
+                // Increment the rin count and read the writer bits
+                let w = lock.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+                self.state = ReadState::Waiting(w);
+                w
+            }
+            ReadState::Waiting(w) => w,
+            ReadState::Done => panic!("AsyncPFLockReadFuture polled after completion"),
+        };
+
+        // Wait if there is a writer present (PRES is set), until either PRES
+        // and/or PHID flips
+        if w & PRES != 0 && !lock.phase_over(w, cx) {
+            return Poll::Pending;
+        }
+
+        self.state = ReadState::Done;
+        Poll::Ready(AsyncPFLockReadGuard { lock })
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncPFLockReadFuture<'_, T> {
+    fn drop(&mut self) {
+        if let ReadState::Waiting(w) = self.state {
+            if !try_withdraw_reader(&self.lock.rin, w) {
+                self.lock.unlock_shared();
+            }
+        }
+    }
+}
+
+enum WriteState {
+    Start,
+    /// Holding writer ticket `wticket`, waiting for its turn
+    Queued(usize),
+    /// Writer bits set, waiting for rout to reach `rticket`
+    Draining(usize),
+    Done,
+}
+
+/// The future returned by [`AsyncPFLock::write`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct AsyncPFLockWriteFuture<'a, T: ?Sized> {
+    lock: &'a AsyncPFLock<T>,
+    state: WriteState,
+}
+
+impl<'a, T: ?Sized> Future for AsyncPFLockWriteFuture<'a, T> {
+    type Output = AsyncPFLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.lock;
+
+        loop {
+            match self.state {
+                WriteState::Start => {
+                    let wticket = lock.win.fetch_add(1, Ordering::Relaxed);
+                    self.state = WriteState::Queued(wticket);
+                }
+                WriteState::Queued(wticket) => {
+                    // Wait until it is my turn to write-lock the resource
+                    if !lock.wticket_served(wticket, cx) {
+                        return Poll::Pending;
+                    }
+
+                    // Set the write-bits of rin to indicate this writer is here,
+                    // flipping the phase ID as in `RawPFLock`
+                    let rticket = lock.rin.fetch_xor(WBITS, Ordering::SeqCst) & ZERO_MASK;
+                    self.state = WriteState::Draining(rticket);
+                }
+                WriteState::Draining(rticket) => {
+                    // Wait until all current readers have finished
+                    if !lock.readers_done(rticket, cx) {
+                        return Poll::Pending;
+                    }
+
+                    self.state = WriteState::Done;
+                    return Poll::Ready(AsyncPFLockWriteGuard { lock });
+                }
+                WriteState::Done => panic!("AsyncPFLockWriteFuture polled after completion"),
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncPFLockWriteFuture<'_, T> {
+    fn drop(&mut self) {
+        match self.state {
+            WriteState::Queued(wticket) => self.lock.abandon_wticket(wticket),
+            WriteState::Draining(rticket) => self.lock.abandon_phase(rticket),
+            WriteState::Start | WriteState::Done => {}
+        }
+    }
+}
+
+/// A read lock on an [`AsyncPFLock`], released when dropped.
+#[must_use = "if unused the AsyncPFLock will immediately unlock"]
+pub struct AsyncPFLockReadGuard<'a, T: ?Sized> {
+    lock: &'a AsyncPFLock<T>,
+}
+
+impl<T: ?Sized> Deref for AsyncPFLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncPFLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock_shared();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AsyncPFLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A write lock on an [`AsyncPFLock`], released when dropped.
+#[must_use = "if unused the AsyncPFLock will immediately unlock"]
+pub struct AsyncPFLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a AsyncPFLock<T>,
+}
+
+impl<T: ?Sized> Deref for AsyncPFLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for AsyncPFLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for AsyncPFLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock_exclusive();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AsyncPFLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
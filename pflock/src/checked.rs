@@ -0,0 +1,116 @@
+use super::{spin_loop, try_withdraw_reader, Ordering, RawPFLock, PRES, RINC, WBITS, ZERO_MASK};
+use lock_api::{GuardSend, RawRwLock, RwLock};
+
+/// Reader bound of a `RawPFCheckedLock` unless given otherwise.
+///
+/// Far more readers than threads ever hold or wait for a lock at once, so
+/// reaching it almost always means leaked read guards.
+pub const DEFAULT_MAX_READERS: usize = u16::MAX as usize;
+
+/// Largest reader bound a `RawPFCheckedLock` accepts.
+///
+/// `rin` and `rout` count readers in steps of `RINC`, so the number of readers
+/// in the lock, `(rin - rout) / RINC`, wraps around after `usize::MAX >> 8`
+/// readers and a writer would see the lock as free. Half of that range is left
+/// as headroom for readers that have incremented `rin` but not been checked yet.
+const MAX_BOUND: usize = usize::MAX >> 9;
+
+/// A phase-fair reader-writer lock with a bounded number of reader slots.
+///
+/// `RawPFCheckedLock` is a [`RawPFLock`] that checks the reader count on every
+/// read-lock. A reader that would exceed `MAX_READERS` (readers holding the
+/// lock plus readers waiting for it) is turned away before it can wrap the
+/// reader count: `try_lock_shared` fails and `lock_shared` panics. This
+/// catches leaked read guards, e.g. from `mem::forget`, long before they
+/// corrupt the writer bits.
+///
+/// The bound costs one extra load of `rout` per read-lock. Writers behave
+/// exactly as in `RawPFLock`.
+pub struct RawPFCheckedLock<const MAX_READERS: usize = DEFAULT_MAX_READERS> {
+    lock: RawPFLock,
+}
+
+impl<const MAX_READERS: usize> RawPFCheckedLock<MAX_READERS> {
+    const BOUND_OK: () = assert!(
+        MAX_READERS > 0 && MAX_READERS <= MAX_BOUND,
+        "MAX_READERS must be between 1 and usize::MAX >> 9"
+    );
+
+    /// The underlying lock, e.g. for its health-check methods.
+    pub fn inner(&self) -> &RawPFLock {
+        &self.lock
+    }
+
+    /// Take a reader slot and return the writer bits of rin, or give the slot
+    /// back and return `None` if all `MAX_READERS` slots are taken.
+    fn take_slot(&self) -> Option<usize> {
+        let () = Self::BOUND_OK;
+
+        let rin = self.lock.rin.fetch_add(RINC, Ordering::Acquire);
+        let rout = self.lock.rout.load(Ordering::Relaxed);
+        let readers = ((rin & ZERO_MASK).wrapping_sub(rout) as isize).max(0) as usize / RINC;
+
+        if readers >= MAX_READERS {
+            // Leave through rout only once admitted, see `try_withdraw_reader`
+            let w = rin & WBITS;
+            if w & PRES == 0 || !try_withdraw_reader(&self.lock.rin, w) {
+                unsafe { self.lock.unlock_shared() };
+            }
+            None
+        } else {
+            Some(rin & WBITS)
+        }
+    }
+}
+
+unsafe impl<const MAX_READERS: usize> RawRwLock for RawPFCheckedLock<MAX_READERS> {
+    const INIT: Self = RawPFCheckedLock {
+        lock: RawPFLock::INIT,
+    };
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        let w = match self.take_slot() {
+            Some(w) => w,
+            None => panic!(
+                "pflock: reader count overflow ({} readers allowed)",
+                MAX_READERS
+            ),
+        };
+
+        // Spin (wait) if there is a writer present (PRES is set), until either
+        // PRES and/or PHID flips
+        while (w & PRES != 0) && (w == (self.lock.rin.load(Ordering::Acquire) & WBITS)) {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.lock.unlock_shared()
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let w = match self.take_slot() {
+            Some(w) => w,
+            None => return false,
+        };
+
+        w & PRES == 0 || !try_withdraw_reader(&self.lock.rin, w)
+    }
+
+    fn lock_exclusive(&self) {
+        self.lock.lock_exclusive()
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.lock.unlock_exclusive()
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.lock.try_lock_exclusive()
+    }
+}
+
+/// A phase-fair reader-writer lock that detects reader-count overflow.
+pub type PFCheckedLock<T> = RwLock<RawPFCheckedLock, T>;
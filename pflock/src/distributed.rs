@@ -0,0 +1,352 @@
+use super::{
+    spin_loop, try_take_wticket, try_withdraw_reader, AtomicUsize, Ordering, PRES, RINC, WBITS,
+    ZERO_MASK,
+};
+use lock_api::{
+    GuardNoSend, RawRwLock, RawRwLockDowngrade, RawRwLockTimed, RawRwLockUpgrade,
+    RawRwLockUpgradeDowngrade, RwLock,
+};
+use std::time::{Duration, Instant};
+
+/// Number of reader slots of a `RawPFDistLock` unless given otherwise.
+pub const DEFAULT_SLOTS: usize = 64;
+
+/// One reader in/out counter pair, alone on its cache line(s).
+#[repr(align(128))]
+struct Slot {
+    rin: AtomicUsize,
+    rout: AtomicUsize,
+}
+
+impl Slot {
+    // Only used to build the slot array of `RawPFDistLock::INIT`
+    #[cfg(not(loom))]
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Slot = Slot {
+        rin: AtomicUsize::new(0),
+        rout: AtomicUsize::new(0),
+    };
+}
+
+/// Source of slot indices: each thread takes the next one the first time it
+/// read-locks any `RawPFDistLock`.
+#[cfg(not(loom))]
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(loom))]
+thread_local! {
+    static THREAD_SLOT: usize = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+}
+
+// loom resets these for every execution it explores
+#[cfg(loom)]
+loom::lazy_static! {
+    static ref NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+}
+
+#[cfg(loom)]
+loom::thread_local! {
+    static THREAD_SLOT: usize = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A phase-fair reader-writer lock with distributed reader counters.
+///
+/// In [`RawPFLock`](crate::RawPFLock) every reader does a `fetch_add` on the
+/// single `rin` word, so its cache line bounces between all reading CPUs.
+/// `RawPFDistLock` splits `rin`/`rout` into `SLOTS` cache-line padded pairs.
+/// Each thread always uses the same slot (threads are numbered as they first
+/// read-lock and wrap around `SLOTS`), so readers pinned to different CPUs
+/// touch different cache lines.
+///
+/// Writers still queue on a single `win`/`wout` ticket pair. A writer sets its
+/// writer bits in every slot before waiting for the readers of each slot to
+/// leave, so the phase-fair blocking bounds of `RawPFLock` are unchanged. The
+/// cost moves to the writer, which touches all `SLOTS` cache lines.
+///
+/// A read guard has to be released on the thread that took it, since it is
+/// counted in that thread's slot, so guards of this lock are not `Send`.
+pub struct RawPFDistLock<const SLOTS: usize = DEFAULT_SLOTS> {
+    slots: [Slot; SLOTS],
+    win: AtomicUsize,
+    wout: AtomicUsize,
+}
+
+impl<const SLOTS: usize> RawPFDistLock<SLOTS> {
+    const SLOTS_OK: () = assert!(SLOTS > 0, "a RawPFDistLock needs at least one slot");
+
+    /// A new, unlocked lock. Replaces `RawRwLock::INIT` under `--cfg loom`,
+    /// like `RawPFLock::new`.
+    #[cfg(loom)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RawPFDistLock {
+            slots: std::array::from_fn(|_| Slot {
+                rin: AtomicUsize::new(0),
+                rout: AtomicUsize::new(0),
+            }),
+            win: AtomicUsize::new(0),
+            wout: AtomicUsize::new(0),
+        }
+    }
+
+    /// Index of the reader slot of the calling thread.
+    fn slot_index(&self) -> usize {
+        let () = Self::SLOTS_OK;
+        THREAD_SLOT.with(|slot| *slot) % SLOTS
+    }
+
+    /// The reader slot of the calling thread.
+    fn slot(&self) -> &Slot {
+        &self.slots[self.slot_index()]
+    }
+
+    /// Wait until it is `wticket`'s turn to write-lock the resource.
+    fn wait_for_wticket(&self, wticket: usize) {
+        while wticket != self.wout.load(Ordering::Acquire) {
+            spin_loop();
+        }
+    }
+
+    /// Set the write-bits of every slot to start a writer phase and return
+    /// the reader tickets to wait for.
+    ///
+    /// As in `RawPFLock`, every slot keeps its phase ID in rin and each writer
+    /// phase flips it, so a reader never sees the bits it waits on again. A
+    /// phase is never given up once its bits are set in a slot: the readers
+    /// that arrived in it are only counted by the next one.
+    fn set_wbits(&self) -> [usize; SLOTS] {
+        let mut rtickets = [0; SLOTS];
+        for (slot, rticket) in self.slots.iter().zip(rtickets.iter_mut()) {
+            *rticket = slot.rin.fetch_xor(WBITS, Ordering::Relaxed) & ZERO_MASK;
+        }
+        rtickets
+    }
+
+    /// Clear the writer present bit of every slot, releasing the readers
+    /// blocked by the current writer phase.
+    fn clear_wbits(&self) {
+        for slot in self.slots.iter() {
+            slot.rin.fetch_and(!PRES, Ordering::Release);
+        }
+    }
+
+    /// Whether no reader holds or waits for the lock, other than one read
+    /// lock of the calling thread if `own` is set. Writers check this before
+    /// setting any write-bits, since they cannot take them back.
+    fn no_readers(&self, own: Option<usize>) -> bool {
+        self.slots.iter().enumerate().all(|(i, slot)| {
+            let held = if own == Some(i) { RINC } else { 0 };
+            let rout = slot.rout.load(Ordering::Acquire);
+            slot.rin.load(Ordering::Relaxed) & ZERO_MASK == rout.wrapping_add(held)
+        })
+    }
+
+    /// Whether the readers of every slot have caught up with `rtickets`.
+    fn readers_done(&self, rtickets: &[usize; SLOTS]) -> bool {
+        self.slots
+            .iter()
+            .zip(rtickets.iter())
+            .all(|(slot, rticket)| *rticket == slot.rout.load(Ordering::Acquire))
+    }
+
+    fn wait_for_phase(slot: &Slot, w: usize) {
+        // Spin (wait) if there is a writer present (PRES is set), until either
+        // PRES and/or PHID flips
+        while (w & PRES != 0) && (w == (slot.rin.load(Ordering::Acquire) & WBITS)) {
+            spin_loop();
+        }
+    }
+}
+
+unsafe impl<const SLOTS: usize> RawRwLock for RawPFDistLock<SLOTS> {
+    #[cfg(not(loom))]
+    const INIT: Self = RawPFDistLock {
+        slots: [Slot::INIT; SLOTS],
+        win: AtomicUsize::new(0),
+        wout: AtomicUsize::new(0),
+    };
+    #[cfg(loom)]
+    const INIT: Self = panic!("use RawPFDistLock::new under loom");
+
+    type GuardMarker = GuardNoSend;
+
+    fn lock_shared(&self) {
+        let slot = self.slot();
+        let w = slot.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+        Self::wait_for_phase(slot, w);
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.slot().rout.fetch_add(RINC, Ordering::Release);
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let slot = self.slot();
+        let w = slot.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+
+        w & PRES == 0 || !try_withdraw_reader(&slot.rin, w)
+    }
+
+    fn lock_exclusive(&self) {
+        let wticket = self.win.fetch_add(1, Ordering::Relaxed);
+        self.wait_for_wticket(wticket);
+
+        let rtickets = self.set_wbits();
+        while !self.readers_done(&rtickets) {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.clear_wbits();
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        if try_take_wticket(&self.win, &self.wout).is_none() {
+            return false;
+        }
+
+        // Decide before setting any write-bits. Readers that arrive before
+        // they are set in their slot are waited for.
+        if !self.no_readers(None) {
+            self.wout.fetch_add(1, Ordering::Release);
+            return false;
+        }
+
+        let rtickets = self.set_wbits();
+        while !self.readers_done(&rtickets) {
+            spin_loop();
+        }
+
+        true
+    }
+}
+
+unsafe impl<const SLOTS: usize> RawRwLockUpgrade for RawPFDistLock<SLOTS> {
+    fn lock_upgradable(&self) {
+        let wticket = self.win.fetch_add(1, Ordering::Relaxed);
+        self.wait_for_wticket(wticket);
+
+        // No writer bits are set while we hold the writer ticket
+        self.slot().rin.fetch_add(RINC, Ordering::Relaxed);
+    }
+
+    fn try_lock_upgradable(&self) -> bool {
+        if try_take_wticket(&self.win, &self.wout).is_none() {
+            return false;
+        }
+
+        self.slot().rin.fetch_add(RINC, Ordering::Relaxed);
+        true
+    }
+
+    unsafe fn unlock_upgradable(&self) {
+        self.slot().rout.fetch_add(RINC, Ordering::Release);
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+
+    unsafe fn upgrade(&self) {
+        // Start our writer phase, then return our own read lock
+        let rtickets = self.set_wbits();
+        self.slot().rout.fetch_add(RINC, Ordering::Relaxed);
+
+        while !self.readers_done(&rtickets) {
+            spin_loop();
+        }
+    }
+
+    unsafe fn try_upgrade(&self) -> bool {
+        // Succeed only if we are the last reader left, and decide before
+        // setting any write-bits, as in `try_lock_exclusive`
+        if !self.no_readers(Some(self.slot_index())) {
+            return false;
+        }
+
+        self.upgrade();
+        true
+    }
+}
+
+unsafe impl<const SLOTS: usize> RawRwLockDowngrade for RawPFDistLock<SLOTS> {
+    unsafe fn downgrade(&self) {
+        self.downgrade_to_upgradable();
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+}
+
+unsafe impl<const SLOTS: usize> RawRwLockUpgradeDowngrade for RawPFDistLock<SLOTS> {
+    unsafe fn downgrade_upgradable(&self) {
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+
+    unsafe fn downgrade_to_upgradable(&self) {
+        // Turn the write-bits of our own slot into a reader ticket and clear
+        // them everywhere else
+        let own = self.slot_index();
+        for (i, slot) in self.slots.iter().enumerate() {
+            if i == own {
+                slot.rin.fetch_add(RINC - PRES, Ordering::Release);
+            } else {
+                slot.rin.fetch_and(!PRES, Ordering::Release);
+            }
+        }
+    }
+}
+
+unsafe impl<const SLOTS: usize> RawRwLockTimed for RawPFDistLock<SLOTS> {
+    type Duration = Duration;
+    type Instant = Instant;
+
+    fn try_lock_shared_for(&self, timeout: Duration) -> bool {
+        self.try_lock_shared_until(Instant::now() + timeout)
+    }
+
+    fn try_lock_shared_until(&self, timeout: Instant) -> bool {
+        let slot = self.slot();
+        let w = slot.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+
+        while (w & PRES != 0) && (w == (slot.rin.load(Ordering::Acquire) & WBITS)) {
+            if Instant::now() >= timeout {
+                return !try_withdraw_reader(&slot.rin, w);
+            }
+            spin_loop();
+        }
+
+        true
+    }
+
+    fn try_lock_exclusive_for(&self, timeout: Duration) -> bool {
+        self.try_lock_exclusive_until(Instant::now() + timeout)
+    }
+
+    /// Like `RawPFLock`, a timed writer waits for the writer queue to drain
+    /// instead of queueing, and for the readers to leave before it sets any
+    /// write-bits.
+    fn try_lock_exclusive_until(&self, timeout: Instant) -> bool {
+        while try_take_wticket(&self.win, &self.wout).is_none() {
+            if Instant::now() >= timeout {
+                return false;
+            }
+            spin_loop();
+        }
+
+        while !self.no_readers(None) {
+            if Instant::now() >= timeout {
+                self.wout.fetch_add(1, Ordering::Release);
+                return false;
+            }
+            spin_loop();
+        }
+
+        let rtickets = self.set_wbits();
+        while !self.readers_done(&rtickets) {
+            spin_loop();
+        }
+
+        true
+    }
+}
+
+/// A phase-fair reader-writer lock with per-thread reader counters.
+pub type PFDistLock<T> = RwLock<RawPFDistLock, T>;
@@ -31,6 +31,109 @@
 //! } // write lock is dropped here
 //! ```
 //!
+//! # Raw locking
+//!
+//! Code that pairs lock and unlock calls itself, e.g. calls inserted by a
+//! compiler, can use a `RawPFLock` without a guard. `RawPFLock::new` is a
+//! `const fn`, so the lock can live in a `static`.
+//!
+//! ```
+//! use pflock::RawPFLock;
+//!
+//! static LOCK: RawPFLock = RawPFLock::new();
+//!
+//! LOCK.read_lock();
+//! unsafe { LOCK.read_unlock() };
+//! LOCK.write_lock();
+//! unsafe { LOCK.write_unlock() };
+//! ```
+//!
+//! # Upgradable reads
+//!
+//! An upgradable read lock holds the next writer ticket while reading, so it
+//! excludes writers and other upgraders but not plain readers. Upgrading starts
+//! the upgrader's writer phase without releasing its read, so no other writer
+//! can slip in between. Readers still wait for at most one writer phase.
+//!
+//! ```
+//! use pflock::{PFLock, PFLockUpgradableReadGuard};
+//!
+//! let lock = PFLock::new(5);
+//!
+//! let u = lock.upgradable_read();
+//! let r = lock.read();
+//! assert_eq!(*u, *r);
+//! drop(r);
+//!
+//! let mut w = PFLockUpgradableReadGuard::upgrade(u);
+//! *w += 1;
+//! assert_eq!(*w, 6);
+//! ```
+//!
+//! A writer can also downgrade to a read lock, e.g. to keep reading a value it
+//! just published. The write phase turns straight into a read, so no other
+//! writer can get in between.
+//!
+//! ```
+//! use pflock::{PFLock, PFLockWriteGuard};
+//!
+//! let lock = PFLock::new(5);
+//!
+//! let mut w = lock.write();
+//! *w += 1;
+//! let r1 = PFLockWriteGuard::downgrade(w);
+//! let r2 = lock.read();
+//! assert_eq!(*r1, 6);
+//! assert_eq!(*r2, 6);
+//! ```
+//!
+//! # Timed acquisition
+//!
+//! `try_read_for`, `try_write_for` and their `_until` variants spin until the lock
+//! is acquired or the deadline passes. A timed writer only takes a writer ticket
+//! once no other writer holds or waits for the lock, so giving up never leaves a
+//! gap in the writer queue. It can be overtaken by writers that block with
+//! `write`. It starts its writer phase only once the readers have left, so a
+//! timed writer that gives up never made a reader wait.
+//!
+//! # Bounded readers
+//!
+//! `rin` counts readers in its upper bits, so enough leaked or concurrent read
+//! guards would eventually wrap the reader count. `PFCheckedLock` bounds the number
+//! of readers and turns away the reader that would exceed it: `try_read` fails and
+//! `read` panics. `RawPFLock::reader_count`, `writer_queue_len` and
+//! `is_locked_exclusive` report the lock state for health checks; `PFLockExt`
+//! exposes them on a `PFLock` directly.
+//!
+//! # Scalable reads
+//!
+//! Every reader of a `PFLock` increments the same `rin` word, so its cache line
+//! bounces between reading CPUs. `PFDistLock` splits the reader counters into
+//! cache-line padded per-thread slots; writers set their phase in every slot and
+//! wait for all of them. Compare the two with `cargo bench`.
+//!
+//! # Blocking statistics
+//!
+//! With the `stats` cargo feature, every `RawPFLock` records how long readers and
+//! writers spun, how many writer phases a reader waited for and how deep the
+//! writer queue was. `PFLockExt::stats` returns a snapshot with a log2 histogram
+//! of spin times, to check measured blocking against the paper's bounds.
+//!
+//! # Serialization
+//!
+//! With the `serde` cargo feature, every `lock_api` lock in the crate, i.e. all
+//! but `AsyncPFLock`, implements `Serialize` and `Deserialize` when its data
+//! does. As with parking_lot's `RwLock`, serializing takes a read lock and
+//! deserializing builds an unlocked lock around the data.
+//!
+//! # Other reader-writer locks
+//!
+//! For comparison, the crate also implements the other RW locks analysed in the
+//! paper: the task-fair `TFLock`, the reader-preference `RPLock` and the
+//! writer-preference `WPLock`. All of them implement `lock_api::RawRwLock`, so
+//! the tests in `tests/read_write.rs` and `tests/siblings.rs` and the benchmark in
+//! `benches/throughput.rs` run against every lock.
+//!
 //! # Spin vs. suspend
 //!
 //! `PFLock` is a spinlock specifically targeted at **short critical sections** and
@@ -43,15 +146,111 @@
 //! > are considered [11, 15]. Based on these trends (and due to space
 //! > constraints), we restrict our focus to short resources in this paper and
 //! > delegate RW synchronization of long resources to future work.
+//!
+//! For long critical sections, `PFParkLock` follows the same phase-fair protocol
+//! but parks blocked threads with `parking_lot_core` after a short spin. Readers
+//! blocked by a writer phase are woken together when it ends.
+//!
+//! # Preemption control
+//!
+//! The blocking bounds of the paper assume that a lock holder runs until it
+//! releases the lock. `PFPreemptLock<P, T>` calls the `enter` and `exit` hooks of
+//! a `PreemptionControl` `P` around every critical section, from before a thread
+//! starts spinning until it released the lock. `NoPreemptionControl` does
+//! nothing; on Linux, `PriorityCeiling<PRIORITY>` runs the thread under
+//! `SCHED_FIFO` at the lock's ceiling priority in between, which needs
+//! `CAP_SYS_NICE`. `PriorityCeiling::new` checks for it once, before the lock is
+//! built with `RawPFPreemptLock::with_control`.
+//!
+//! # Async tasks
+//!
+//! `AsyncPFLock` runs the same protocol for async tasks: `read().await` and
+//! `write().await` return `Pending` instead of spinning and are woken through
+//! their wakers. The readers of one phase are woken together when the writer
+//! before them leaves. The lock works with any executor and has no
+//! dependencies.
+//!
+//! ```
+//! use pflock::AsyncPFLock;
+//!
+//! async fn bump(counter: &AsyncPFLock<u32>) -> u32 {
+//!     *counter.write().await += 1;
+//!     *counter.read().await
+//! }
+//! ```
+//!
+//! # Several locks at once
+//!
+//! Tasks that take several locks in different orders can deadlock. The
+//! [`multi`] module takes a set of locks, each requested for reading or
+//! writing, acquires them in address order and returns one guard that
+//! releases all of them when dropped.
+
+use lock_api::{
+    GuardSend, RawRwLock, RawRwLockDowngrade, RawRwLockTimed, RawRwLockUpgrade,
+    RawRwLockUpgradeDowngrade, RwLock,
+};
+use std::time::{Duration, Instant};
+
+// Under `--cfg loom` the core lock runs on loom's atomics, so `tests/loom.rs`
+// can check every interleaving of it
+#[cfg(loom)]
+use loom::{
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+#[cfg(not(loom))]
+use std::{
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+mod async_lock;
+// `RawPFCheckedLock::INIT` needs `RawPFLock::INIT`, which loom can't provide
+#[cfg(not(loom))]
+mod checked;
+mod distributed;
+pub mod multi;
+// `RawPFParkLock::INIT` needs atomics that can be built in a `const` as well
+#[cfg(not(loom))]
+mod park;
+#[cfg(not(loom))]
+mod preempt;
+mod reader_pref;
+#[cfg(feature = "stats")]
+pub mod stats;
+mod task_fair;
+mod writer_pref;
 
-use lock_api::{GuardSend, RawRwLock, RwLock};
-use std::sync::atomic::{spin_loop_hint, AtomicUsize, Ordering};
+pub use async_lock::{
+    AsyncPFLock, AsyncPFLockReadFuture, AsyncPFLockReadGuard, AsyncPFLockWriteFuture,
+    AsyncPFLockWriteGuard,
+};
+#[cfg(not(loom))]
+pub use checked::{PFCheckedLock, RawPFCheckedLock, DEFAULT_MAX_READERS};
+pub use distributed::{PFDistLock, RawPFDistLock, DEFAULT_SLOTS};
+#[cfg(not(loom))]
+pub use park::{PFParkLock, RawPFParkLock};
+#[cfg(all(not(loom), target_os = "linux"))]
+pub use preempt::PriorityCeiling;
+#[cfg(not(loom))]
+pub use preempt::{NoPreemptionControl, PFPreemptLock, PreemptionControl, RawPFPreemptLock};
+pub use reader_pref::{RPLock, RawRPLock};
+pub use task_fair::{RawTFLock, TFLock};
+pub use writer_pref::{RawWPLock, WPLock};
 
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+
+// `repr(C)` so that `pflock-ffi` can give C a struct of the same layout
+#[repr(C)]
 pub struct RawPFLock {
     rin: AtomicUsize,
     rout: AtomicUsize,
     win: AtomicUsize,
     wout: AtomicUsize,
+    #[cfg(feature = "stats")]
+    stats: stats::Recorder,
 }
 
 const RINC: usize = 0x100; // reader increment
@@ -61,88 +260,493 @@ const PHID: usize = 0x1; // phase ID bit
 
 const ZERO_MASK: usize = !255usize;
 
+// The phase ID is kept in rin rather than taken from the writer ticket: every
+// writer phase flips it when it sets PRES, and releasing the lock clears only
+// PRES. A reader waiting on writer bits `w` is counted by the next writer
+// phase, which publishes different bits and cannot end before the reader
+// leaves, so no later phase can show it `w` again. The parity of the writer
+// ticket does not give this, since upgradable locks take tickets without
+// starting a writer phase.
+
+// Memory ordering: a writer's critical section is published by the `Release`
+// RMWs on rin (to the readers of the next phase) and wout (to the next writer),
+// and a reader's by its `Release` increment of rout, which the writer waiting
+// for it acquires. Every other write to the counters is an RMW, so it extends
+// those release sequences and the `Relaxed` ticket updates cannot cut an edge.
+// `tests/loom.rs` model-checks this.
+
+// The steps below are shared by every lock in the crate that runs this
+// protocol on its own counters.
+
+/// Take the next writer ticket, but only if no other writer holds the lock or
+/// is queued for it. A ticket taken from `win` can only be given up once it is
+/// served, by incrementing `wout`, so every attempt that may give up has to go
+/// through here instead of queueing.
+pub(crate) fn try_take_wticket(win: &AtomicUsize, wout: &AtomicUsize) -> Option<usize> {
+    let wticket = wout.load(Ordering::Acquire);
+    win.compare_exchange(wticket, wticket + 1, Ordering::Relaxed, Ordering::Relaxed)
+        .ok()
+}
+
+/// Start the writer phase of the holder of the writer ticket, but only if no
+/// reader holds or waits for the lock, i.e. `rin` has caught up with `rout`.
+/// Returns whether the writer phase started.
+///
+/// A writer that may give up must decide before it publishes its bits: a
+/// reader arriving during the phase waits on them, and only the next writer
+/// phase counts it, so a published phase can never be taken back.
+pub(crate) fn try_start_phase(rin: &AtomicUsize, rout: &AtomicUsize) -> bool {
+    // Only the holder of the writer ticket changes the writer bits, so the
+    // phase ID we read stays put. `SeqCst` since `PFParkLock` and
+    // `AsyncPFLock` order the writer bits against `rout` to not miss wake-ups.
+    let expected = rout.load(Ordering::SeqCst) | (rin.load(Ordering::Relaxed) & PHID);
+    rin.compare_exchange(
+        expected,
+        expected ^ WBITS,
+        Ordering::SeqCst,
+        Ordering::Relaxed,
+    )
+    .is_ok()
+}
+
+/// Take back the `rin` increment of a reader that arrived during writer phase
+/// `w` and gives up waiting for it. Returns `false` if the phase ended first,
+/// in which case the reader holds the lock after all.
+///
+/// The reader cannot leave through rout instead: the writer of phase `w` did
+/// not count it in its reader ticket, so the extra rout increment could let
+/// that writer in while an earlier reader still holds the lock. Taking it back
+/// is safe while the writer bits are still `w`: phase `w` is never given up
+/// once its bits are published, so no later writer has set its bits and
+/// counted the reader yet. Nor can the bits come back to `w` later on, since
+/// the next writer phase flips the phase ID and waits for this reader to
+/// leave.
+pub(crate) fn try_withdraw_reader(rin: &AtomicUsize, w: usize) -> bool {
+    let mut current = rin.load(Ordering::Acquire);
+    while current & WBITS == w {
+        match rin.compare_exchange_weak(
+            current,
+            current.wrapping_sub(RINC),
+            Ordering::Relaxed,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => return true,
+            Err(actual) => current = actual,
+        }
+    }
+    false
+}
+
+impl RawPFLock {
+    /// A new, unlocked lock, e.g. for a `static`.
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        <Self as RawRwLock>::INIT
+    }
+
+    /// A new, unlocked lock. loom's atomics cannot be built in a `const`, so
+    /// under `--cfg loom` this replaces `RawRwLock::INIT`.
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        RawPFLock {
+            rin: AtomicUsize::new(0),
+            rout: AtomicUsize::new(0),
+            win: AtomicUsize::new(0),
+            wout: AtomicUsize::new(0),
+            #[cfg(feature = "stats")]
+            stats: stats::Recorder::INIT,
+        }
+    }
+
+    /// Block until the lock is read-locked, without a guard.
+    ///
+    /// For lock and unlock calls that are paired by something other than a
+    /// guard's scope, e.g. calls inserted by a compiler pass.
+    pub fn read_lock(&self) {
+        self.lock_shared()
+    }
+
+    /// Release a read lock taken with [`read_lock`](Self::read_lock).
+    ///
+    /// # Safety
+    ///
+    /// The calling thread must hold a read lock on this lock.
+    pub unsafe fn read_unlock(&self) {
+        self.unlock_shared()
+    }
+
+    /// Block until the lock is write-locked, without a guard.
+    pub fn write_lock(&self) {
+        self.lock_exclusive()
+    }
+
+    /// Release a write lock taken with [`write_lock`](Self::write_lock).
+    ///
+    /// # Safety
+    ///
+    /// The calling thread must hold the write lock on this lock.
+    pub unsafe fn write_unlock(&self) {
+        self.unlock_exclusive()
+    }
+}
+
+impl Default for RawPFLock {
+    fn default() -> Self {
+        RawPFLock::new()
+    }
+}
+
 unsafe impl RawRwLock for RawPFLock {
+    #[cfg(not(loom))]
     const INIT: RawPFLock = RawPFLock {
         rin: AtomicUsize::new(0),
         rout: AtomicUsize::new(0),
         win: AtomicUsize::new(0),
         wout: AtomicUsize::new(0),
+        #[cfg(feature = "stats")]
+        stats: stats::Recorder::INIT,
     };
+    #[cfg(loom)]
+    const INIT: RawPFLock = panic!("use RawPFLock::new under loom");
 
     type GuardMarker = GuardSend;
 
     fn lock_shared(&self) {
+        #[cfg(feature = "stats")]
+        let start = Instant::now();
+
         // Increment the rin count and read the writer bits
-        let w = self.rin.fetch_add(RINC, Ordering::Relaxed) & WBITS;
+        let w = self.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
 
-        // Spin (wait) if there is a writer present (w != 0), until either PRES
-        // and/or PHID flips
-        while (w != 0) && (w == (self.rin.load(Ordering::Relaxed) & WBITS)) {
-            spin_loop_hint();
+        // A reader that saw writer bits waits for that writer phase, and for
+        // every later one it still sees the same bits in: a writer release
+        // (wout increment) acquired before a load of rin that shows `w` again
+        #[cfg(feature = "stats")]
+        let (mut phases, mut wout) = ((w & PRES != 0) as usize, self.wout.load(Ordering::Acquire));
+
+        // Spin (wait) if there is a writer present (PRES is set), until either
+        // PRES and/or PHID flips
+        loop {
+            #[cfg(feature = "stats")]
+            let seen = self.wout.load(Ordering::Acquire);
+            if w & PRES == 0 || w != (self.rin.load(Ordering::Acquire) & WBITS) {
+                break;
+            }
+            #[cfg(feature = "stats")]
+            {
+                phases += seen.wrapping_sub(wout);
+                wout = seen;
+            }
+            spin_loop();
         }
+
+        #[cfg(feature = "stats")]
+        self.stats.record_read(start.elapsed(), phases);
     }
 
     unsafe fn unlock_shared(&self) {
         // Increment rout to mark the read-lock returned
-        self.rout.fetch_add(RINC, Ordering::Relaxed);
+        self.rout.fetch_add(RINC, Ordering::Release);
     }
 
     fn try_lock_shared(&self) -> bool {
-        let w = self.rin.fetch_add(RINC, Ordering::Relaxed) & WBITS;
+        let w = self.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
 
-        if w == 0 || w != (self.rin.load(Ordering::Relaxed) & WBITS) {
-            true
-        } else {
-            self.rout.fetch_add(RINC, Ordering::Relaxed);
-            false
-        }
+        w & PRES == 0 || !try_withdraw_reader(&self.rin, w)
     }
 
     fn lock_exclusive(&self) {
+        #[cfg(feature = "stats")]
+        let start = Instant::now();
+
         // Wait until it is my turn to write-lock the resource
         let wticket = self.win.fetch_add(1, Ordering::Relaxed);
-        while wticket != self.wout.load(Ordering::Relaxed) {
-            spin_loop_hint();
+
+        #[cfg(feature = "stats")]
+        let queue = wticket.wrapping_sub(self.wout.load(Ordering::Relaxed));
+
+        while wticket != self.wout.load(Ordering::Acquire) {
+            spin_loop();
         }
 
         // Set the write-bits of rin to indicate this writer is here
-        let w = PRES | (wticket & PHID);
-        let rticket = self.rin.fetch_add(w, Ordering::Relaxed);
+        let rticket = self.start_phase();
 
         // Wait until all current readers have finished (i.e. rout catches up)
-        while rticket != self.rout.load(Ordering::Relaxed) {
-            spin_loop_hint();
+        while rticket != self.rout.load(Ordering::Acquire) {
+            spin_loop();
         }
+
+        #[cfg(feature = "stats")]
+        self.stats.record_write(start.elapsed(), queue);
     }
 
     unsafe fn unlock_exclusive(&self) {
-        // Clear the least-significant byte of rin
-        self.rin.fetch_and(ZERO_MASK, Ordering::Relaxed);
+        // Clear the writer present bit of rin, keeping the phase ID
+        self.rin.fetch_and(!PRES, Ordering::Release);
 
         // Increment wout to indicate this write has released the lock
         // Only one writer should ever be here
-        self.wout.fetch_add(1, Ordering::Relaxed);
+        self.wout.fetch_add(1, Ordering::Release);
     }
 
     fn try_lock_exclusive(&self) -> bool {
+        if try_take_wticket(&self.win, &self.wout).is_none() {
+            return false;
+        }
+
+        // Set the writer bits only if no reader holds or waits for the lock, in
+        // one step. Once published they cannot be taken back: a reader arriving
+        // meanwhile waits on them, and is only counted by the next writer phase.
+        if !try_start_phase(&self.rin, &self.rout) {
+            // Pass the ticket on without a writer phase, as an upgradable read
+            // does
+            self.wout.fetch_add(1, Ordering::Release);
+            return false;
+        }
+
+        true
+    }
+}
+
+impl RawPFLock {
+    /// Number of readers that hold the lock or are waiting for a writer phase
+    /// to end.
+    pub fn reader_count(&self) -> usize {
+        let rin = self.rin.load(Ordering::Relaxed) & ZERO_MASK;
+        let rout = self.rout.load(Ordering::Relaxed);
+
+        // A reader may leave between the two loads, so rout can run ahead of
+        // the rin we read
+        (rin.wrapping_sub(rout) as isize).max(0) as usize / RINC
+    }
+
+    /// Number of writers (and upgradable readers) that hold a writer ticket or
+    /// are queued for one.
+    pub fn writer_queue_len(&self) -> usize {
+        let wout = self.wout.load(Ordering::Relaxed);
+        let win = self.win.load(Ordering::Relaxed);
+
+        (win.wrapping_sub(wout) as isize).max(0) as usize
+    }
+
+    /// Whether a writer phase is in progress, i.e. a writer holds the lock or
+    /// is waiting for the last readers of the previous phase to leave.
+    pub fn is_locked_exclusive(&self) -> bool {
+        self.rin.load(Ordering::Relaxed) & PRES != 0
+    }
+
+    /// A snapshot of the blocking statistics recorded so far.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// Clear the blocking statistics, e.g. after a warm-up phase.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset()
+    }
+
+    /// Start the writer phase of the holder of the writer ticket: set PRES and
+    /// flip the phase ID in one step. Returns the reader ticket, i.e. the rin
+    /// count of the readers this writer has to wait for.
+    fn start_phase(&self) -> usize {
+        // PRES is clear, since the previous writer cleared it before handing
+        // on the ticket, so flipping both writer bits sets it
+        self.rin.fetch_xor(WBITS, Ordering::Relaxed) & ZERO_MASK
+    }
+}
+
+unsafe impl RawRwLockTimed for RawPFLock {
+    type Duration = Duration;
+    type Instant = Instant;
+
+    fn try_lock_shared_for(&self, timeout: Duration) -> bool {
+        self.try_lock_shared_until(Instant::now() + timeout)
+    }
+
+    fn try_lock_shared_until(&self, timeout: Instant) -> bool {
+        let w = self.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+
+        while (w & PRES != 0) && (w == (self.rin.load(Ordering::Acquire) & WBITS)) {
+            if Instant::now() >= timeout {
+                return !try_withdraw_reader(&self.rin, w);
+            }
+            spin_loop();
+        }
+
+        true
+    }
+
+    fn try_lock_exclusive_for(&self, timeout: Duration) -> bool {
+        self.try_lock_exclusive_until(Instant::now() + timeout)
+    }
+
+    /// A timed writer does not queue in `win`/`wout`: it waits for the writer
+    /// queue to drain and then takes the ticket that is being served. Writers
+    /// that block with `lock_exclusive` may therefore overtake it.
+    ///
+    /// It then sets its writer bits only once the readers have left, as
+    /// `try_lock_exclusive` does, and gives the ticket back if they do not
+    /// leave in time. A writer phase is never given up once it has started,
+    /// so readers that arrive meanwhile are not held up by an attempt that
+    /// fails.
+    fn try_lock_exclusive_until(&self, timeout: Instant) -> bool {
+        while try_take_wticket(&self.win, &self.wout).is_none() {
+            if Instant::now() >= timeout {
+                return false;
+            }
+            spin_loop();
+        }
+
+        while !try_start_phase(&self.rin, &self.rout) {
+            if Instant::now() >= timeout {
+                // Pass the ticket on without a writer phase
+                self.wout.fetch_add(1, Ordering::Release);
+                return false;
+            }
+            spin_loop();
+        }
+
+        true
+    }
+}
+
+unsafe impl RawRwLockUpgrade for RawPFLock {
+    fn lock_upgradable(&self) {
+        // Take a writer ticket so that no other writer or upgrader can enter,
+        // but do not set the writer bits: other readers are still admitted.
         let wticket = self.win.fetch_add(1, Ordering::Relaxed);
-        if wticket != self.wout.load(Ordering::Relaxed) {
-            self.wout.fetch_add(1, Ordering::Relaxed);
+        while wticket != self.wout.load(Ordering::Acquire) {
+            spin_loop();
+        }
+
+        // The previous writer cleared its bits of rin before incrementing
+        // wout, so this read lock never has to wait for a writer phase.
+        self.rin.fetch_add(RINC, Ordering::Relaxed);
+    }
+
+    fn try_lock_upgradable(&self) -> bool {
+        if try_take_wticket(&self.win, &self.wout).is_none() {
             return false;
         }
-        let w = PRES | (wticket & PHID);
-        let rticket = self.rin.fetch_add(w, Ordering::Relaxed);
 
-        if rticket != self.rout.load(Ordering::Relaxed) {
-            self.rin.fetch_and(ZERO_MASK, Ordering::Relaxed);
-            self.wout.fetch_add(1, Ordering::Relaxed);
+        self.rin.fetch_add(RINC, Ordering::Relaxed);
+        true
+    }
+
+    unsafe fn unlock_upgradable(&self) {
+        // Return the read lock, then hand the writer ticket to the next writer
+        self.rout.fetch_add(RINC, Ordering::Release);
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+
+    unsafe fn upgrade(&self) {
+        // This upgrader already holds the writer ticket, so it starts the next
+        // writer phase: set the write-bits of rin to block incoming readers
+        let rticket = self.start_phase();
+
+        // Return our own read lock and wait until all other readers have
+        // finished (i.e. rout catches up)
+        self.rout.fetch_add(RINC, Ordering::Relaxed);
+        while rticket != self.rout.load(Ordering::Acquire) {
+            spin_loop();
+        }
+    }
+
+    unsafe fn try_upgrade(&self) -> bool {
+        // Set the write-bits only if we are the last reader left, in one step.
+        // A failed attempt must not publish them: a reader arriving meanwhile
+        // would wait on them, and might not see them cleared before the next
+        // `upgrade` sets the same bits again and counts it in its ticket.
+        // The phase ID we read stays put, as in `try_start_phase`
+        let rin = self.rout.load(Ordering::Acquire).wrapping_add(RINC)
+            | (self.rin.load(Ordering::Relaxed) & PHID);
+        if self
+            .rin
+            .compare_exchange(rin, rin ^ WBITS, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
             return false;
         }
 
+        self.rout.fetch_add(RINC, Ordering::Relaxed);
         true
     }
 }
 
+unsafe impl RawRwLockDowngrade for RawPFLock {
+    unsafe fn downgrade(&self) {
+        // Turn the writer present bit of rin into a reader ticket in one step.
+        // Readers waiting on this writer phase see it clear and enter with us.
+        self.rin.fetch_add(RINC - PRES, Ordering::Release);
+
+        // Let the next writer in; it waits for our read lock like any other
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+}
+
+unsafe impl RawRwLockUpgradeDowngrade for RawPFLock {
+    unsafe fn downgrade_upgradable(&self) {
+        // Keep the read lock and give up the writer ticket
+        self.wout.fetch_add(1, Ordering::Release);
+    }
+
+    unsafe fn downgrade_to_upgradable(&self) {
+        // Same as `downgrade`, but keep the writer ticket
+        self.rin.fetch_add(RINC - PRES, Ordering::Release);
+    }
+}
+
 /// A phase-fair reader-writer lock.
 pub type PFLock<T> = RwLock<RawPFLock, T>;
+
+/// Lock state and statistics of a [`PFLock`], without going through the
+/// `unsafe` [`RwLock::raw`].
+pub trait PFLockExt {
+    /// See [`RawPFLock::reader_count`].
+    fn reader_count(&self) -> usize;
+
+    /// See [`RawPFLock::writer_queue_len`].
+    fn writer_queue_len(&self) -> usize;
+
+    /// See [`RawPFLock::is_locked_exclusive`].
+    fn is_locked_exclusive(&self) -> bool;
+
+    /// See [`RawPFLock::stats`].
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> Stats;
+
+    /// See [`RawPFLock::reset_stats`].
+    #[cfg(feature = "stats")]
+    fn reset_stats(&self);
+}
+
+impl<T: ?Sized> PFLockExt for PFLock<T> {
+    fn reader_count(&self) -> usize {
+        unsafe { self.raw() }.reader_count()
+    }
+
+    fn writer_queue_len(&self) -> usize {
+        unsafe { self.raw() }.writer_queue_len()
+    }
+
+    fn is_locked_exclusive(&self) -> bool {
+        unsafe { self.raw() }.is_locked_exclusive()
+    }
+
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> Stats {
+        unsafe { self.raw() }.stats()
+    }
+
+    #[cfg(feature = "stats")]
+    fn reset_stats(&self) {
+        unsafe { self.raw() }.reset_stats()
+    }
+}
 pub type PFLockGuard<'a, T> = lock_api::MutexGuard<'a, RawPFLock, T>;
+pub type PFLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawPFLock, T>;
+pub type PFLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawPFLock, T>;
+pub type PFLockUpgradableReadGuard<'a, T> = lock_api::RwLockUpgradableReadGuard<'a, RawPFLock, T>;
@@ -0,0 +1,187 @@
+//! Acquiring several `PFLock`s at once.
+//!
+//! A task that needs more than one lock can deadlock against another task that
+//! takes the same locks in a different order. [`lock`] takes a set of
+//! requests, each a lock and whether to [`read`] or [`write`] it, and always
+//! acquires them in one global order: by the address of the lock. Since every
+//! caller climbs the same order, no cycle of waiting tasks can form.
+//!
+//! The returned [`MultiGuard`] holds all of the locks and releases them when
+//! it is dropped. Reach the data through it with [`MultiGuard::get`] and
+//! [`MultiGuard::get_mut`].
+//!
+//! ```
+//! use pflock::multi;
+//! use pflock::PFLock;
+//!
+//! let config = PFLock::new(2);
+//! let limits = PFLock::new(10);
+//! let total = PFLock::new(0);
+//!
+//! let mut guard = multi::lock(&[
+//!     multi::read(&config),
+//!     multi::read(&limits),
+//!     multi::write(&total),
+//! ]);
+//! let value = *guard.get(&config) * *guard.get(&limits);
+//! *guard.get_mut(&total) += value;
+//! drop(guard);
+//!
+//! assert_eq!(*total.read(), 20);
+//! ```
+//!
+//! The order only protects locks taken through this module: do not call
+//! [`lock`] while holding another `PFLock`. Each lock still waits as it would
+//! on its own, so a reader blocks for at most one writer phase per lock.
+
+use super::{PFLock, RawPFLock};
+use lock_api::RawRwLock;
+
+/// How a lock is requested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Read,
+    Write,
+}
+
+/// One lock of a multi-lock request, made with [`read`] or [`write`].
+#[derive(Clone, Copy)]
+pub struct Request<'a> {
+    lock: &'a RawPFLock,
+    mode: Mode,
+}
+
+impl Request<'_> {
+    /// The global acquisition order of the lock.
+    fn addr(&self) -> usize {
+        self.lock as *const RawPFLock as usize
+    }
+
+    fn acquire(&self) {
+        match self.mode {
+            Mode::Read => self.lock.lock_shared(),
+            Mode::Write => self.lock.lock_exclusive(),
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        match self.mode {
+            Mode::Read => self.lock.try_lock_shared(),
+            Mode::Write => self.lock.try_lock_exclusive(),
+        }
+    }
+
+    unsafe fn release(&self) {
+        match self.mode {
+            Mode::Read => self.lock.unlock_shared(),
+            Mode::Write => self.lock.unlock_exclusive(),
+        }
+    }
+}
+
+/// Request `lock` for reading.
+pub fn read<T: ?Sized>(lock: &PFLock<T>) -> Request<'_> {
+    Request {
+        lock: unsafe { lock.raw() },
+        mode: Mode::Read,
+    }
+}
+
+/// Request `lock` for writing.
+pub fn write<T: ?Sized>(lock: &PFLock<T>) -> Request<'_> {
+    Request {
+        lock: unsafe { lock.raw() },
+        mode: Mode::Write,
+    }
+}
+
+/// Sort `requests` into the global order and merge requests for the same
+/// lock; a lock requested for both reading and writing is written.
+fn sorted<'a>(requests: &[Request<'a>]) -> Vec<Request<'a>> {
+    let mut sorted = requests.to_vec();
+    sorted.sort_by_key(Request::addr);
+    sorted.dedup_by(|next, kept| {
+        if next.addr() != kept.addr() {
+            return false;
+        }
+        if next.mode == Mode::Write {
+            kept.mode = Mode::Write;
+        }
+        true
+    });
+    sorted
+}
+
+/// Acquire every requested lock, in address order, blocking as needed.
+pub fn lock<'a>(requests: &[Request<'a>]) -> MultiGuard<'a> {
+    let held = sorted(requests);
+    for request in held.iter() {
+        request.acquire();
+    }
+    MultiGuard { held }
+}
+
+/// Acquire every requested lock without blocking, or none of them.
+pub fn try_lock<'a>(requests: &[Request<'a>]) -> Option<MultiGuard<'a>> {
+    let mut guard = MultiGuard {
+        held: Vec::with_capacity(requests.len()),
+    };
+    for request in sorted(requests) {
+        if !request.try_acquire() {
+            // Dropping the guard releases what we have so far
+            return None;
+        }
+        guard.held.push(request);
+    }
+    Some(guard)
+}
+
+/// Several locks held together, released in reverse order when dropped.
+#[must_use = "if unused the locks will immediately unlock"]
+pub struct MultiGuard<'a> {
+    /// In address order
+    held: Vec<Request<'a>>,
+}
+
+impl MultiGuard<'_> {
+    /// The mode `lock` is held in, if it is held by this guard.
+    pub fn mode<T: ?Sized>(&self, lock: &PFLock<T>) -> Option<Mode> {
+        let addr = read(lock).addr();
+        self.held
+            .binary_search_by_key(&addr, Request::addr)
+            .ok()
+            .map(|i| self.held[i].mode)
+    }
+
+    /// The data of `lock`.
+    ///
+    /// # Panics
+    ///
+    /// If `lock` is not held by this guard.
+    pub fn get<'g, T: ?Sized>(&'g self, lock: &'g PFLock<T>) -> &'g T {
+        match self.mode(lock) {
+            Some(_) => unsafe { &*lock.data_ptr() },
+            None => panic!("pflock: lock is not held by this MultiGuard"),
+        }
+    }
+
+    /// The data of `lock`, for writing.
+    ///
+    /// # Panics
+    ///
+    /// If `lock` is not held for writing by this guard.
+    pub fn get_mut<'g, T: ?Sized>(&'g mut self, lock: &'g PFLock<T>) -> &'g mut T {
+        match self.mode(lock) {
+            Some(Mode::Write) => unsafe { &mut *lock.data_ptr() },
+            _ => panic!("pflock: lock is not held for writing by this MultiGuard"),
+        }
+    }
+}
+
+impl Drop for MultiGuard<'_> {
+    fn drop(&mut self) {
+        for request in self.held.iter().rev() {
+            unsafe { request.release() };
+        }
+    }
+}
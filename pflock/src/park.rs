@@ -0,0 +1,212 @@
+use super::{
+    try_start_phase, try_take_wticket, try_withdraw_reader, AtomicUsize, Ordering, PRES, RINC,
+    WBITS, ZERO_MASK,
+};
+use lock_api::{GuardSend, RawRwLock, RwLock};
+use parking_lot_core::{FilterOp, ParkToken, SpinWait, DEFAULT_PARK_TOKEN, DEFAULT_UNPARK_TOKEN};
+
+/// A phase-fair reader-writer lock that suspends blocked threads instead of
+/// spinning, for RW synchronization of long resources.
+///
+/// `RawPFParkLock` follows the same `rin`/`rout`/`win`/`wout` protocol as
+/// [`RawPFLock`](crate::RawPFLock), but a thread that has to wait spins only
+/// briefly and is then parked with `parking_lot_core`. Each counter has its
+/// own wait queue:
+///
+/// - readers waiting for a writer phase to end park on `rin` and are all woken
+///   together when that writer releases the lock, so a reader phase starts at
+///   once,
+/// - writers waiting for their ticket park on `wout` and are woken one at a
+///   time, in ticket order,
+/// - a writer waiting for the readers of the current phase to leave parks on
+///   `rout` and is woken by the last of them.
+pub struct RawPFParkLock {
+    rin: AtomicUsize,
+    rout: AtomicUsize,
+    win: AtomicUsize,
+    wout: AtomicUsize,
+}
+
+impl RawPFParkLock {
+    // Parking keys: waiters are queued on the address of the counter they wait for
+    fn rin_key(&self) -> usize {
+        &self.rin as *const _ as usize
+    }
+
+    fn rout_key(&self) -> usize {
+        &self.rout as *const _ as usize
+    }
+
+    fn wout_key(&self) -> usize {
+        &self.wout as *const _ as usize
+    }
+
+    /// Wait until the writer bits of rin are no longer `w`, i.e. the writer
+    /// phase this reader arrived in is over.
+    fn wait_for_phase(&self, w: usize) {
+        let mut spinwait = SpinWait::new();
+        while w == (self.rin.load(Ordering::Acquire) & WBITS) {
+            if spinwait.spin() {
+                continue;
+            }
+            let validate = || w == (self.rin.load(Ordering::Acquire) & WBITS);
+            unsafe {
+                parking_lot_core::park(
+                    self.rin_key(),
+                    validate,
+                    || {},
+                    |_, _| {},
+                    DEFAULT_PARK_TOKEN,
+                    None,
+                );
+            }
+        }
+    }
+
+    /// Wait until it is `wticket`'s turn to write-lock the resource.
+    fn wait_for_wticket(&self, wticket: usize) {
+        let mut spinwait = SpinWait::new();
+        while wticket != self.wout.load(Ordering::Acquire) {
+            if spinwait.spin() {
+                continue;
+            }
+            let validate = || wticket != self.wout.load(Ordering::Acquire);
+            unsafe {
+                parking_lot_core::park(
+                    self.wout_key(),
+                    validate,
+                    || {},
+                    |_, _| {},
+                    ParkToken(wticket),
+                    None,
+                );
+            }
+        }
+    }
+
+    /// Wait until all readers that arrived before `rticket` have finished.
+    fn wait_for_readers(&self, rticket: usize) {
+        let mut spinwait = SpinWait::new();
+        while rticket != self.rout.load(Ordering::SeqCst) {
+            if spinwait.spin() {
+                continue;
+            }
+            let validate = || rticket != self.rout.load(Ordering::SeqCst);
+            unsafe {
+                parking_lot_core::park(
+                    self.rout_key(),
+                    validate,
+                    || {},
+                    |_, _| {},
+                    ParkToken(rticket),
+                    None,
+                );
+            }
+        }
+    }
+
+    /// Wake the parked thread holding `token` on `key`, if there is one.
+    fn unpark_token(key: usize, token: usize) {
+        unsafe {
+            parking_lot_core::unpark_filter(
+                key,
+                |ParkToken(t)| {
+                    if t == token {
+                        FilterOp::Unpark
+                    } else {
+                        FilterOp::Skip
+                    }
+                },
+                |_| DEFAULT_UNPARK_TOKEN,
+            );
+        }
+    }
+
+    /// Start the writer phase of the holder of the writer ticket and return
+    /// its reader ticket, as in `RawPFLock`.
+    fn start_phase(&self) -> usize {
+        self.rin.fetch_xor(WBITS, Ordering::SeqCst) & ZERO_MASK
+    }
+
+    /// Hand the writer ticket on to the next writer in line.
+    fn release_wticket(&self) {
+        let next = self.wout.fetch_add(1, Ordering::Release) + 1;
+        Self::unpark_token(self.wout_key(), next);
+    }
+}
+
+unsafe impl RawRwLock for RawPFParkLock {
+    const INIT: RawPFParkLock = RawPFParkLock {
+        rin: AtomicUsize::new(0),
+        rout: AtomicUsize::new(0),
+        win: AtomicUsize::new(0),
+        wout: AtomicUsize::new(0),
+    };
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        // Increment the rin count and read the writer bits
+        let w = self.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+
+        // Block if there is a writer present, until either PRES and/or PHID flips
+        if w & PRES != 0 {
+            self.wait_for_phase(w);
+        }
+    }
+
+    unsafe fn unlock_shared(&self) {
+        // Increment rout to mark the read-lock returned
+        let rout = self.rout.fetch_add(RINC, Ordering::SeqCst) + RINC;
+
+        // If a writer is present it may be parked waiting for this reader
+        if self.rin.load(Ordering::SeqCst) & PRES != 0 {
+            Self::unpark_token(self.rout_key(), rout);
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let w = self.rin.fetch_add(RINC, Ordering::Acquire) & WBITS;
+
+        w & PRES == 0 || !try_withdraw_reader(&self.rin, w)
+    }
+
+    fn lock_exclusive(&self) {
+        // Wait until it is my turn to write-lock the resource
+        let wticket = self.win.fetch_add(1, Ordering::Relaxed);
+        self.wait_for_wticket(wticket);
+
+        // Set the write-bits of rin to indicate this writer is here
+        let rticket = self.start_phase();
+
+        // Wait until all current readers have finished (i.e. rout catches up)
+        self.wait_for_readers(rticket);
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        // Clear the writer present bit and release all readers blocked by this
+        // phase
+        self.rin.fetch_and(!PRES, Ordering::Release);
+        parking_lot_core::unpark_all(self.rin_key(), DEFAULT_UNPARK_TOKEN);
+
+        self.release_wticket();
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        // Only take a writer ticket if no writer holds or waits for the lock
+        if try_take_wticket(&self.win, &self.wout).is_none() {
+            return false;
+        }
+
+        // Set the writer bits only if no reader holds or waits for the lock
+        if !try_start_phase(&self.rin, &self.rout) {
+            self.release_wticket();
+            return false;
+        }
+
+        true
+    }
+}
+
+/// A phase-fair reader-writer lock that parks blocked threads.
+pub type PFParkLock<T> = RwLock<RawPFParkLock, T>;
@@ -0,0 +1,293 @@
+use super::RawPFLock;
+use lock_api::{GuardNoSend, RawRwLock, RwLock};
+
+/// Hooks called around every critical section of a [`RawPFPreemptLock`].
+///
+/// The blocking bounds of a phase-fair lock assume that lock holders are not
+/// preempted: a reader or writer descheduled while it holds the lock, or while
+/// it is queued for it, delays every task behind it by however long it stays
+/// off the CPU. An implementation keeps the scheduler away from the thread
+/// between `enter` and `exit`, e.g. by disabling preemption or raising the
+/// thread's priority.
+pub trait PreemptionControl {
+    /// The control a new lock starts with.
+    const INIT: Self;
+
+    /// Called on the acquiring thread before it starts waiting for the lock.
+    fn enter(&self);
+
+    /// Called on the same thread after it released the lock, or after it gave
+    /// up acquiring it.
+    fn exit(&self);
+}
+
+/// A [`PreemptionControl`] that does nothing, for systems where lock holders
+/// can't be preempted or the bounds don't matter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoPreemptionControl;
+
+impl PreemptionControl for NoPreemptionControl {
+    const INIT: Self = NoPreemptionControl;
+
+    fn enter(&self) {}
+
+    fn exit(&self) {}
+}
+
+/// A phase-fair reader-writer lock that calls a [`PreemptionControl`] around
+/// each critical section.
+///
+/// `enter` runs before a thread starts spinning for the lock and `exit` after
+/// it released it, so the thread is protected both while it waits in the queue
+/// and while it holds the lock. As the hooks act on the calling thread, the
+/// guards of this lock are not `Send`.
+pub struct RawPFPreemptLock<P: PreemptionControl = NoPreemptionControl> {
+    lock: RawPFLock,
+    control: P,
+}
+
+impl<P: PreemptionControl> RawPFPreemptLock<P> {
+    /// A new, unlocked lock with the given preemption control, e.g. one
+    /// checked when it was made. Wrap it with `PFPreemptLock::const_new`.
+    pub const fn with_control(control: P) -> Self {
+        RawPFPreemptLock {
+            lock: RawPFLock::INIT,
+            control,
+        }
+    }
+
+    /// The underlying lock, e.g. for its health-check methods.
+    pub fn inner(&self) -> &RawPFLock {
+        &self.lock
+    }
+
+    /// The preemption control of this lock.
+    pub fn control(&self) -> &P {
+        &self.control
+    }
+
+    /// Run `acquire` between the hooks, leaving through `exit` if it fails.
+    fn try_acquire(&self, acquire: impl FnOnce(&RawPFLock) -> bool) -> bool {
+        self.control.enter();
+        let acquired = acquire(&self.lock);
+        if !acquired {
+            self.control.exit();
+        }
+        acquired
+    }
+}
+
+unsafe impl<P: PreemptionControl> RawRwLock for RawPFPreemptLock<P> {
+    const INIT: Self = RawPFPreemptLock {
+        lock: RawPFLock::INIT,
+        control: P::INIT,
+    };
+
+    type GuardMarker = GuardNoSend;
+
+    fn lock_shared(&self) {
+        self.control.enter();
+        self.lock.lock_shared();
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.lock.unlock_shared();
+        self.control.exit();
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        self.try_acquire(RawPFLock::try_lock_shared)
+    }
+
+    fn lock_exclusive(&self) {
+        self.control.enter();
+        self.lock.lock_exclusive();
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.lock.unlock_exclusive();
+        self.control.exit();
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.try_acquire(RawPFLock::try_lock_exclusive)
+    }
+}
+
+/// A phase-fair reader-writer lock with preemption control.
+pub type PFPreemptLock<P, T> = RwLock<RawPFPreemptLock<P>, T>;
+
+#[cfg(target_os = "linux")]
+pub use self::ceiling::PriorityCeiling;
+
+#[cfg(target_os = "linux")]
+mod ceiling {
+    use super::PreemptionControl;
+    use libc::c_int;
+    use std::cell::RefCell;
+    use std::{io, mem};
+
+    /// A [`PreemptionControl`] that runs the thread under `SCHED_FIFO` at
+    /// `PRIORITY` while it waits for or holds the lock.
+    ///
+    /// This is the priority-ceiling approach: give `PRIORITY` the highest
+    /// priority of any task that uses the lock, and no task that might take it
+    /// can preempt the holder. A thread that already runs under a real-time
+    /// policy at or above the ceiling keeps its priority. Each thread records
+    /// the ceilings of the locks it is in and runs at the highest of them, so
+    /// locks with different ceilings may be nested and released in any order.
+    /// The thread's own policy and priority are restored when it leaves the
+    /// last of them.
+    ///
+    /// `PRIORITY` must be a valid `SCHED_FIFO` priority, 1 to 99 on Linux.
+    ///
+    /// Changing the scheduling policy needs `CAP_SYS_NICE` or a matching
+    /// `RLIMIT_RTPRIO`. [`PriorityCeiling::new`] checks this once, so build
+    /// the lock around it with [`RawPFPreemptLock::with_control`]:
+    ///
+    /// ```no_run
+    /// use pflock::{PFPreemptLock, PriorityCeiling, RawPFPreemptLock};
+    ///
+    /// let ceiling = PriorityCeiling::<10>::new().expect("no real-time privilege");
+    /// let lock = PFPreemptLock::const_new(RawPFPreemptLock::with_control(ceiling), 0);
+    /// ```
+    ///
+    /// [`RawPFPreemptLock::with_control`]: crate::RawPFPreemptLock::with_control
+    ///
+    /// # Panics
+    ///
+    /// A lock built from `PreemptionControl::INIT` instead, e.g. with
+    /// `PFPreemptLock::new`, is not checked: acquiring it panics if the thread
+    /// may not switch to the ceiling. Releasing a lock never panics, since it
+    /// only ever lowers the priority.
+    #[derive(Clone, Copy, Debug)]
+    pub struct PriorityCeiling<const PRIORITY: i32> {
+        _private: (),
+    }
+
+    impl<const PRIORITY: i32> PriorityCeiling<PRIORITY> {
+        const PRIORITY_OK: () = assert!(
+            PRIORITY >= 1 && PRIORITY <= 99,
+            "PRIORITY must be a SCHED_FIFO priority between 1 and 99"
+        );
+
+        /// A priority ceiling, after checking that the calling thread may run
+        /// under `SCHED_FIFO` at `PRIORITY`. The check switches the thread to
+        /// the ceiling and back, and fails with the error of that switch,
+        /// usually `PermissionDenied`.
+        pub fn new() -> io::Result<Self> {
+            let () = Self::PRIORITY_OK;
+            let current = get_sched()?;
+            set_sched((libc::SCHED_FIFO, PRIORITY))?;
+            set_sched(current)?;
+            Ok(PriorityCeiling { _private: () })
+        }
+    }
+
+    impl<const PRIORITY: i32> PreemptionControl for PriorityCeiling<PRIORITY> {
+        const INIT: Self = PriorityCeiling { _private: () };
+
+        fn enter(&self) {
+            let () = Self::PRIORITY_OK;
+            if let Err(err) = CEILINGS.with(|ceilings| ceilings.borrow_mut().enter(PRIORITY)) {
+                panic!(
+                    "pflock: can't switch to priority ceiling {}: {}",
+                    PRIORITY, err
+                );
+            }
+        }
+
+        fn exit(&self) {
+            CEILINGS.with(|ceilings| ceilings.borrow_mut().exit(PRIORITY));
+        }
+    }
+
+    /// Scheduling policy and priority of a thread.
+    type Sched = (c_int, c_int);
+
+    /// The ceilings the current thread runs under.
+    struct Ceilings {
+        /// The thread's own scheduling, from before its first ceiling
+        base: Sched,
+        /// The scheduling currently applied
+        current: Sched,
+        /// Ceilings of the locks the thread is in, in no particular order
+        held: Vec<i32>,
+    }
+
+    thread_local! {
+        static CEILINGS: RefCell<Ceilings> = const {
+            RefCell::new(Ceilings {
+                base: (libc::SCHED_OTHER, 0),
+                current: (libc::SCHED_OTHER, 0),
+                held: Vec::new(),
+            })
+        };
+    }
+
+    impl Ceilings {
+        /// Switch to `ceiling` if it is above the ceilings held, and record it
+        /// only once that succeeded.
+        fn enter(&mut self, ceiling: i32) -> io::Result<()> {
+            if self.held.is_empty() {
+                self.base = get_sched()?;
+                self.current = self.base;
+            }
+            let highest = self.held.iter().copied().fold(ceiling, i32::max);
+            self.switch_to(self.target(Some(highest)))?;
+            self.held.push(ceiling);
+            Ok(())
+        }
+
+        fn exit(&mut self, ceiling: i32) {
+            if let Some(i) = self.held.iter().position(|&c| c == ceiling) {
+                self.held.swap_remove(i);
+            }
+            // Lowering the priority needs no privilege. Should it fail anyway,
+            // the thread stays at the higher priority rather than failing the
+            // unlock, and the next switch tries again.
+            let _ = self.switch_to(self.target(self.held.iter().copied().max()));
+        }
+
+        /// The scheduling for running under `ceiling`, or under none.
+        fn target(&self, ceiling: Option<i32>) -> Sched {
+            let (policy, priority) = self.base;
+            let realtime = policy == libc::SCHED_FIFO || policy == libc::SCHED_RR;
+            match ceiling {
+                Some(ceiling) if !realtime || priority < ceiling => (libc::SCHED_FIFO, ceiling),
+                _ => self.base,
+            }
+        }
+
+        fn switch_to(&mut self, target: Sched) -> io::Result<()> {
+            if target != self.current {
+                set_sched(target)?;
+                self.current = target;
+            }
+            Ok(())
+        }
+    }
+
+    fn get_sched() -> io::Result<Sched> {
+        let mut policy = 0;
+        let mut param: libc::sched_param = unsafe { mem::zeroed() };
+        let err =
+            unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) };
+        check(err)?;
+        Ok((policy, param.sched_priority))
+    }
+
+    fn set_sched((policy, priority): Sched) -> io::Result<()> {
+        let mut param: libc::sched_param = unsafe { mem::zeroed() };
+        param.sched_priority = priority;
+        let err = unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) };
+        check(err)
+    }
+
+    fn check(err: c_int) -> io::Result<()> {
+        match err {
+            0 => Ok(()),
+            err => Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
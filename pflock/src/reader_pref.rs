@@ -0,0 +1,64 @@
+use lock_api::{GuardSend, RawRwLock, RwLock};
+use std::hint::spin_loop;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A reader-preference reader-writer lock.
+///
+/// Readers announce themselves in the reader count as soon as they arrive, and
+/// a writer can only enter when the count is zero. Readers therefore never
+/// wait for a writer that has not entered yet, but a steady stream of readers
+/// can starve writers.
+pub struct RawRPLock {
+    state: AtomicUsize,
+}
+
+const RP_WACT: usize = 0x1; // writer active bit
+const RP_RINC: usize = 0x2; // reader increment
+
+unsafe impl RawRwLock for RawRPLock {
+    const INIT: RawRPLock = RawRPLock {
+        state: AtomicUsize::new(0),
+    };
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        // Announce this reader, then wait for an active writer to leave
+        self.state.fetch_add(RP_RINC, Ordering::Acquire);
+        while self.state.load(Ordering::Acquire) & RP_WACT != 0 {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.state.fetch_sub(RP_RINC, Ordering::Release);
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        if self.state.fetch_add(RP_RINC, Ordering::Acquire) & RP_WACT != 0 {
+            self.state.fetch_sub(RP_RINC, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    fn lock_exclusive(&self) {
+        // Wait until there are no readers and no writer at all
+        while !self.try_lock_exclusive() {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state.fetch_sub(RP_WACT, Ordering::Release);
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.state
+            .compare_exchange(0, RP_WACT, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+/// A reader-preference reader-writer lock.
+pub type RPLock<T> = RwLock<RawRPLock, T>;
@@ -0,0 +1,171 @@
+//! Blocking-time instrumentation, enabled with the `stats` cargo feature.
+//!
+//! With the feature on, every [`RawPFLock`](crate::RawPFLock) records how long
+//! `lock_shared` and `lock_exclusive` spun, how many writer phases a reader
+//! waited for and how many writers were queued ahead of a writer. The numbers
+//! are meant to be checked against a response-time analysis, e.g. that no
+//! reader ever waits for more than one writer phase.
+//!
+//! ```
+//! use pflock::{PFLock, PFLockExt};
+//!
+//! let lock = PFLock::new(0);
+//! drop(lock.read());
+//! *lock.write() += 1;
+//!
+//! let stats = lock.stats();
+//! assert_eq!(stats.read.count, 1);
+//! assert_eq!(stats.write.count, 1);
+//! assert!(stats.max_reader_phases <= 1);
+//! ```
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Number of histogram buckets. Bucket 0 counts waits shorter than 1ns and
+/// bucket `i` counts waits of `2^(i-1)` to `2^i` ns; the last bucket also
+/// takes everything longer.
+pub const BUCKETS: usize = 40;
+
+/// Spin times of one kind of acquisition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpinStats {
+    /// Number of acquisitions recorded.
+    pub count: u64,
+    /// Longest time spent spinning in a single acquisition.
+    pub max: Duration,
+    /// Number of acquisitions per spin-time bucket, see [`BUCKETS`].
+    pub histogram: [u64; BUCKETS],
+}
+
+impl SpinStats {
+    /// The range of spin times counted in histogram bucket `i`.
+    pub fn bucket_range(i: usize) -> (Duration, Duration) {
+        match i {
+            0 => (Duration::from_nanos(0), Duration::from_nanos(1)),
+            _ if i >= BUCKETS - 1 => (Duration::from_nanos(1 << (BUCKETS - 2)), Duration::MAX),
+            _ => (
+                Duration::from_nanos(1 << (i - 1)),
+                Duration::from_nanos(1 << i),
+            ),
+        }
+    }
+}
+
+impl Default for SpinStats {
+    fn default() -> Self {
+        SpinStats {
+            count: 0,
+            max: Duration::from_nanos(0),
+            histogram: [0; BUCKETS],
+        }
+    }
+}
+
+/// A snapshot of the blocking statistics of one lock.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Time spent spinning in `lock_shared`.
+    pub read: SpinStats,
+    /// Time spent spinning in `lock_exclusive`, both for the writer ticket and
+    /// for the readers of the previous phase.
+    pub write: SpinStats,
+    /// Most writer phases a single reader waited for: the one it arrived in,
+    /// plus each later writer that set the same writer bits before the reader
+    /// saw them change. Phase fairness bounds this by one.
+    pub max_reader_phases: usize,
+    /// Most writers queued ahead of a writer when it arrived.
+    pub max_writer_queue: usize,
+}
+
+// Only used to build the arrays of `Recorder::INIT`
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+struct SpinRecorder {
+    count: AtomicU64,
+    max_ns: AtomicU64,
+    histogram: [AtomicU64; BUCKETS],
+}
+
+impl SpinRecorder {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: SpinRecorder = SpinRecorder {
+        count: ZERO,
+        max_ns: ZERO,
+        histogram: [ZERO; BUCKETS],
+    };
+
+    fn record(&self, spin: Duration) {
+        let ns = spin.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = ((64 - ns.leading_zeros()) as usize).min(BUCKETS - 1);
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max_ns.fetch_max(ns, Ordering::Relaxed);
+        self.histogram[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> SpinStats {
+        let mut stats = SpinStats {
+            count: self.count.load(Ordering::Relaxed),
+            max: Duration::from_nanos(self.max_ns.load(Ordering::Relaxed)),
+            ..SpinStats::default()
+        };
+        for (n, bucket) in stats.histogram.iter_mut().zip(self.histogram.iter()) {
+            *n = bucket.load(Ordering::Relaxed);
+        }
+        stats
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.max_ns.store(0, Ordering::Relaxed);
+        for bucket in self.histogram.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The statistics a `RawPFLock` keeps while the `stats` feature is on.
+pub(crate) struct Recorder {
+    read: SpinRecorder,
+    write: SpinRecorder,
+    max_reader_phases: AtomicUsize,
+    max_writer_queue: AtomicUsize,
+}
+
+impl Recorder {
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub(crate) const INIT: Recorder = Recorder {
+        read: SpinRecorder::INIT,
+        write: SpinRecorder::INIT,
+        max_reader_phases: AtomicUsize::new(0),
+        max_writer_queue: AtomicUsize::new(0),
+    };
+
+    pub(crate) fn record_read(&self, spin: Duration, phases: usize) {
+        self.read.record(spin);
+        self.max_reader_phases.fetch_max(phases, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_write(&self, spin: Duration, queue: usize) {
+        self.write.record(spin);
+        self.max_writer_queue.fetch_max(queue, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            read: self.read.snapshot(),
+            write: self.write.snapshot(),
+            max_reader_phases: self.max_reader_phases.load(Ordering::Relaxed),
+            max_writer_queue: self.max_writer_queue.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.read.reset();
+        self.write.reset();
+        self.max_reader_phases.store(0, Ordering::Relaxed);
+        self.max_writer_queue.store(0, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,89 @@
+use lock_api::{GuardSend, RawRwLock, RwLock};
+use std::hint::spin_loop;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A task-fair (FIFO) reader-writer lock.
+///
+/// This is the centralized fair RW lock of Mellor-Crummey and Scott, the
+/// task-fair lock compared against in the Brandenburg paper. Requests are
+/// served in arrival order; consecutive readers share the lock, but a reader
+/// that arrives after a writer waits for that writer (and so for everything
+/// queued before it). Each of `requests` and `completions` packs a reader
+/// count into its upper half and a writer count into its lower half, so one
+/// `fetch_add` both takes a place in the queue and snapshots everyone ahead.
+pub struct RawTFLock {
+    requests: AtomicU64,
+    completions: AtomicU64,
+}
+
+const TF_RINC: u64 = 1 << 32; // reader increment
+const TF_WINC: u64 = 1; // writer increment
+const TF_WMASK: u64 = TF_RINC - 1; // writer count
+
+unsafe impl RawRwLock for RawTFLock {
+    const INIT: RawTFLock = RawTFLock {
+        requests: AtomicU64::new(0),
+        completions: AtomicU64::new(0),
+    };
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        // Wait until every writer that arrived before us has completed
+        let prev = self.requests.fetch_add(TF_RINC, Ordering::Relaxed) & TF_WMASK;
+        while prev != (self.completions.load(Ordering::Acquire) & TF_WMASK) {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.completions.fetch_add(TF_RINC, Ordering::Release);
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        // Only join the queue if no writer is outstanding
+        let requests = self.requests.load(Ordering::Relaxed);
+        if (requests & TF_WMASK) != (self.completions.load(Ordering::Acquire) & TF_WMASK) {
+            return false;
+        }
+        self.requests
+            .compare_exchange(
+                requests,
+                requests.wrapping_add(TF_RINC),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    fn lock_exclusive(&self) {
+        // Wait until every reader and writer that arrived before us has completed
+        let prev = self.requests.fetch_add(TF_WINC, Ordering::Relaxed);
+        while prev != self.completions.load(Ordering::Acquire) {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.completions.fetch_add(TF_WINC, Ordering::Release);
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        // Only join the queue if it is empty
+        let requests = self.requests.load(Ordering::Relaxed);
+        if requests != self.completions.load(Ordering::Acquire) {
+            return false;
+        }
+        self.requests
+            .compare_exchange(
+                requests,
+                requests.wrapping_add(TF_WINC),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+}
+
+/// A task-fair reader-writer lock.
+pub type TFLock<T> = RwLock<RawTFLock, T>;
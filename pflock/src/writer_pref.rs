@@ -0,0 +1,101 @@
+use lock_api::{GuardSend, RawRwLock, RwLock};
+use std::hint::spin_loop;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A writer-preference reader-writer lock.
+///
+/// Writers announce themselves as soon as they arrive, and a reader can only
+/// enter while no writer is active or waiting. Writers therefore wait at most
+/// for the readers already inside, but a steady stream of writers can starve
+/// readers.
+pub struct RawWPLock {
+    state: AtomicUsize,
+}
+
+const WP_WACT: usize = 0x1; // writer active bit
+const WP_WWAIT: usize = 0x2; // waiting writer increment
+const WP_WWAIT_MASK: usize = 0xfffe; // waiting writer count
+const WP_RINC: usize = 0x1_0000; // reader increment
+const WP_RMASK: usize = !0xffff; // reader count
+
+unsafe impl RawRwLock for RawWPLock {
+    const INIT: RawWPLock = RawWPLock {
+        state: AtomicUsize::new(0),
+    };
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_shared(&self) {
+        self.state.fetch_sub(WP_RINC, Ordering::Release);
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        // Enter only while no writer is active or waiting
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & (WP_WACT | WP_WWAIT_MASK) == 0 {
+            match self.state.compare_exchange_weak(
+                state,
+                state + WP_RINC,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+        false
+    }
+
+    fn lock_exclusive(&self) {
+        // Announce this writer so that new readers hold back
+        self.state.fetch_add(WP_WWAIT, Ordering::Relaxed);
+
+        // Wait for the active writer and the readers already inside to leave
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & (WP_WACT | WP_RMASK) == 0
+                && self
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state - WP_WWAIT + WP_WACT,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                return;
+            }
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state.fetch_sub(WP_WACT, Ordering::Release);
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & (WP_WACT | WP_RMASK) == 0 {
+            match self.state.compare_exchange_weak(
+                state,
+                state + WP_WACT,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+        false
+    }
+}
+
+/// A writer-preference reader-writer lock.
+pub type WPLock<T> = RwLock<RawWPLock, T>;
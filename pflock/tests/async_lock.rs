@@ -0,0 +1,294 @@
+//! Tests for `AsyncPFLock`, driven by a minimal local executor and by polling
+//! futures by hand with counting wakers.
+
+use pflock::AsyncPFLock;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Run `fut` to completion on the current thread, parking while it is pending.
+fn block_on<F: Future>(fut: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = Box::pin(fut);
+    loop {
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// A waker that counts how often it was woken.
+#[derive(Default)]
+struct CountWaker(AtomicUsize);
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl CountWaker {
+    fn woken(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A future polled by hand, with its own counting waker.
+struct Polled<F> {
+    fut: Pin<Box<F>>,
+    waker: Arc<CountWaker>,
+}
+
+impl<F: Future> Polled<F> {
+    fn new(fut: F) -> Self {
+        Polled {
+            fut: Box::pin(fut),
+            waker: Arc::default(),
+        }
+    }
+
+    fn poll(&mut self) -> Poll<F::Output> {
+        let waker = Waker::from(self.waker.clone());
+        self.fut.as_mut().poll(&mut Context::from_waker(&waker))
+    }
+
+    fn woken(&self) -> usize {
+        self.waker.woken()
+    }
+}
+
+#[test]
+fn smoke() {
+    let l = AsyncPFLock::new(());
+    block_on(async {
+        drop(l.read().await);
+        drop(l.write().await);
+        drop((l.read().await, l.read().await));
+        drop(l.write().await);
+    });
+}
+
+#[test]
+fn frob() {
+    const N: u32 = 10;
+    const M: u32 = 1000;
+
+    let r = Arc::new(AsyncPFLock::new(0u32));
+
+    let mut handles = vec![];
+    for i in 0..N {
+        let r = r.clone();
+        handles.push(thread::spawn(move || {
+            block_on(async {
+                for j in 0..M {
+                    if (i + j) % N == 0 {
+                        *r.write().await += 1;
+                    } else {
+                        drop(r.read().await);
+                    }
+                }
+            })
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(*block_on(r.read()), M);
+}
+
+#[test]
+fn try_read_write() {
+    let lock = AsyncPFLock::new(0isize);
+    {
+        let read_guard = lock.try_read().unwrap();
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(read_guard);
+    }
+    {
+        let mut write_guard = lock.try_write().unwrap();
+        *write_guard += 1;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(write_guard);
+    }
+    assert_eq!(lock.try_read().as_deref(), Some(&1));
+    assert_eq!(lock.into_inner(), 1);
+}
+
+#[test]
+fn readers_of_a_phase_are_woken_together() {
+    let lock = AsyncPFLock::new(());
+    let write_guard = lock.try_write().unwrap();
+
+    let mut readers: Vec<_> = (0..4).map(|_| Polled::new(lock.read())).collect();
+    for reader in readers.iter_mut() {
+        assert!(reader.poll().is_pending());
+    }
+
+    drop(write_guard);
+    assert!(readers.iter().all(|reader| reader.woken() == 1));
+
+    let guards: Vec<_> = readers.iter_mut().map(|reader| reader.poll()).collect();
+    assert!(guards.iter().all(Poll::is_ready));
+}
+
+#[test]
+fn reader_before_later_writer() {
+    let lock = AsyncPFLock::new(0);
+    let write_guard = lock.try_write().unwrap();
+
+    let mut reader = Polled::new(lock.read());
+    let mut writer = Polled::new(lock.write());
+    assert!(reader.poll().is_pending());
+    assert!(writer.poll().is_pending());
+
+    // The reader queued during the first writer phase goes next
+    drop(write_guard);
+    let read_guard = match reader.poll() {
+        Poll::Ready(guard) => guard,
+        Poll::Pending => panic!("reader blocked for more than one writer phase"),
+    };
+
+    // The second writer now waits for that reader, and new readers for it
+    assert!(writer.poll().is_pending());
+    let mut late_reader = Polled::new(lock.read());
+    assert!(late_reader.poll().is_pending());
+
+    drop(read_guard);
+    assert_eq!(writer.woken(), 2);
+    match writer.poll() {
+        Poll::Ready(mut guard) => *guard += 1,
+        Poll::Pending => panic!("writer not admitted after the reader phase"),
+    }
+    assert_eq!(late_reader.woken(), 1);
+    assert!(matches!(late_reader.poll(), Poll::Ready(guard) if *guard == 1));
+}
+
+#[test]
+fn writers_are_served_in_order() {
+    let lock = AsyncPFLock::new(Vec::new());
+    let write_guard = lock.try_write().unwrap();
+
+    let mut writers: Vec<_> = (0..3)
+        .map(|i| {
+            let lock = &lock;
+            Polled::new(async move { lock.write().await.push(i) })
+        })
+        .collect();
+    for writer in writers.iter_mut() {
+        assert!(writer.poll().is_pending());
+    }
+
+    drop(write_guard);
+    for (i, writer) in writers.iter_mut().enumerate() {
+        // Only the next writer in line has been woken
+        assert_eq!(writer.woken(), 1, "writer {}", i);
+        assert!(writer.poll().is_ready());
+    }
+
+    assert_eq!(*lock.try_read().unwrap(), [0, 1, 2]);
+}
+
+#[test]
+fn dropped_reader_does_not_block_writers() {
+    let lock = AsyncPFLock::new(());
+    let write_guard = lock.try_write().unwrap();
+
+    let mut reader = Polled::new(lock.read());
+    assert!(reader.poll().is_pending());
+    drop(reader);
+
+    drop(write_guard);
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn dropped_queued_writer_passes_its_turn_on() {
+    let lock = AsyncPFLock::new(());
+    let write_guard = lock.try_write().unwrap();
+
+    let mut dropped = Polled::new(lock.write());
+    let mut next = Polled::new(lock.write());
+    assert!(dropped.poll().is_pending());
+    assert!(next.poll().is_pending());
+    drop(dropped);
+
+    drop(write_guard);
+    assert_eq!(next.woken(), 1);
+    assert!(next.poll().is_ready());
+    drop(next);
+
+    assert!(lock.try_write().is_some());
+}
+
+/// A writer dropped while the readers before it drain has started its phase
+/// already. The last of those readers ends it and releases the readers that
+/// arrived during it.
+#[test]
+fn dropped_draining_writer_phase_ends_with_last_reader() {
+    let lock = AsyncPFLock::new(());
+    let read_guard = lock.try_read().unwrap();
+
+    // The writer sets its bits and waits for the reader
+    let mut writer = Polled::new(lock.write());
+    assert!(writer.poll().is_pending());
+    let mut reader = Polled::new(lock.read());
+    assert!(reader.poll().is_pending());
+
+    drop(writer);
+    assert_eq!(reader.woken(), 0);
+    assert!(reader.poll().is_pending());
+
+    drop(read_guard);
+    assert_eq!(reader.woken(), 1);
+    assert!(reader.poll().is_ready());
+    drop(reader);
+
+    assert!(lock.try_write().is_some());
+}
+
+/// A reader polled several times during one writer phase is woken once.
+#[test]
+fn repolled_reader_is_registered_once() {
+    let lock = AsyncPFLock::new(());
+    let write_guard = lock.try_write().unwrap();
+
+    let mut reader = Polled::new(lock.read());
+    for _ in 0..3 {
+        assert!(reader.poll().is_pending());
+    }
+
+    drop(write_guard);
+    assert_eq!(reader.woken(), 1);
+    assert!(reader.poll().is_ready());
+}
+
+/// A `try_write` that fails because of a reader does not hold up readers
+/// that come after it.
+#[test]
+fn failed_try_write_does_not_block_readers() {
+    let lock = AsyncPFLock::new(());
+    let read_guard = lock.try_read().unwrap();
+
+    assert!(lock.try_write().is_none());
+    let mut reader = Polled::new(lock.read());
+    assert!(reader.poll().is_ready());
+
+    drop(read_guard);
+    assert!(lock.try_write().is_some());
+}
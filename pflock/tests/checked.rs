@@ -0,0 +1,155 @@
+//! Tests for the reader-count bound of `RawPFCheckedLock` and the health-check
+//! methods of `RawPFLock`.
+
+use lock_api::RwLock;
+use pflock::{PFCheckedLock, PFLock, RawPFCheckedLock, DEFAULT_MAX_READERS};
+use std::mem;
+use std::panic;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+type SmallLock<T> = RwLock<RawPFCheckedLock<2>, T>;
+
+#[test]
+fn smoke() {
+    let l = PFCheckedLock::new(());
+    drop(l.read());
+    drop(l.write());
+    drop((l.read(), l.read()));
+    drop(l.write());
+}
+
+#[test]
+fn try_read_fails_when_full() {
+    let lock = SmallLock::new(0);
+
+    let r1 = lock.read();
+    let r2 = lock.try_read();
+    assert!(r2.is_some());
+    assert!(
+        lock.try_read().is_none(),
+        "try_read should fail while all reader slots are taken"
+    );
+    assert_eq!(unsafe { lock.raw() }.inner().reader_count(), 2);
+
+    drop(r1);
+    assert!(lock.try_read().is_some());
+    drop(r2);
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn read_panics_on_overflow() {
+    let lock = SmallLock::new(0);
+
+    // Leak both reader slots
+    mem::forget(lock.read());
+    mem::forget(lock.read());
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _guard = lock.read();
+    }));
+    assert!(result.is_err(), "read should panic when the bound is exceeded");
+
+    // The reader that panicked gave its slot back
+    let raw = unsafe { lock.raw() };
+    assert_eq!(raw.inner().reader_count(), 2);
+
+    unsafe {
+        lock.force_unlock_read();
+        lock.force_unlock_read();
+    }
+    assert_eq!(raw.inner().reader_count(), 0);
+    assert!(lock.try_write().is_some());
+}
+
+/// The default bound is low enough for leaked guards to reach it.
+#[test]
+fn default_bound_turns_readers_away() {
+    let lock = PFCheckedLock::new(0);
+
+    for _ in 0..DEFAULT_MAX_READERS {
+        mem::forget(lock.read());
+    }
+    assert!(lock.try_read().is_none());
+
+    let raw = unsafe { lock.raw() };
+    assert_eq!(raw.inner().reader_count(), DEFAULT_MAX_READERS);
+
+    for _ in 0..DEFAULT_MAX_READERS {
+        unsafe { lock.force_unlock_read() };
+    }
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn health_checks() {
+    let lock = Arc::new(PFLock::new(0));
+    let raw = unsafe { lock.raw() };
+
+    assert_eq!(raw.reader_count(), 0);
+    assert_eq!(raw.writer_queue_len(), 0);
+    assert!(!raw.is_locked_exclusive());
+
+    let (r1, r2) = (lock.read(), lock.read());
+    assert_eq!(raw.reader_count(), 2);
+    assert_eq!(raw.writer_queue_len(), 0);
+    assert!(!raw.is_locked_exclusive());
+    drop((r1, r2));
+
+    let u = lock.upgradable_read();
+    assert_eq!(raw.reader_count(), 1);
+    assert_eq!(raw.writer_queue_len(), 1);
+    assert!(!raw.is_locked_exclusive());
+    drop(u);
+
+    let w = lock.write();
+    assert_eq!(raw.reader_count(), 0);
+    assert_eq!(raw.writer_queue_len(), 1);
+    assert!(raw.is_locked_exclusive());
+
+    // One more writer queued behind
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            *lock.write() += 1;
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(raw.writer_queue_len(), 2);
+
+    drop(w);
+    writer.join().unwrap();
+    assert_eq!(raw.writer_queue_len(), 0);
+    assert!(!raw.is_locked_exclusive());
+    assert_eq!(*lock.read(), 1);
+}
+
+/// A reader turned away at the bound must not count as a departed reader, or
+/// a queued writer enters while the admitted readers still hold the lock.
+#[test]
+fn turned_away_reader_keeps_writer_out() {
+    let lock = Arc::new(SmallLock::new(()));
+    let first = lock.read();
+    let second = lock.read();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _w = lock.write();
+            tx.send(()).unwrap();
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    assert!(lock.try_read().is_none());
+    drop(first);
+    thread::sleep(Duration::from_millis(50));
+    assert!(rx.try_recv().is_err(), "writer entered during a read phase");
+
+    drop(second);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    writer.join().unwrap();
+}
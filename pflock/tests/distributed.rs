@@ -0,0 +1,147 @@
+//! Tests for the phase-fair lock with distributed reader counters.
+
+use lock_api::{RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
+use pflock::{PFDistLock, RawPFDistLock};
+use rand::Rng;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+/// Fewer slots than threads, so that slots are shared.
+type SmallLock<T> = RwLock<RawPFDistLock<2>, T>;
+
+#[test]
+fn shared_slots() {
+    const N: usize = 6;
+    const M: usize = 1000;
+
+    let lock = Arc::new(SmallLock::new(0));
+
+    let mut handles = vec![];
+    for _ in 0..N {
+        let lock = lock.clone();
+        handles.push(thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            let mut writes = 0;
+            for _ in 0..M {
+                if rng.gen_bool(0.1) {
+                    *lock.write() += 1;
+                    writes += 1;
+                } else {
+                    drop(lock.read());
+                }
+            }
+            writes
+        }));
+    }
+
+    let writes: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(*lock.read(), writes);
+}
+
+/// Readers on different slots hold the lock at the same time, and a writer
+/// waits for all of them.
+#[test]
+fn writer_waits_for_every_slot() {
+    const N: usize = 4;
+
+    let lock = Arc::new(PFDistLock::new(0));
+    let barrier = Arc::new(Barrier::new(N + 1));
+
+    let mut readers = vec![];
+    for _ in 0..N {
+        let lock = lock.clone();
+        let barrier = barrier.clone();
+        readers.push(thread::spawn(move || {
+            let r = lock.read();
+            barrier.wait();
+            thread::sleep(Duration::from_millis(100));
+            *r
+        }));
+    }
+    barrier.wait();
+
+    assert!(lock.try_write().is_none());
+    *lock.write() = 1;
+
+    for reader in readers {
+        assert_eq!(reader.join().unwrap(), 0);
+    }
+    assert_eq!(*lock.read(), 1);
+}
+
+#[test]
+fn upgrade_downgrade() {
+    let lock = PFDistLock::new(0);
+
+    let upgrade_guard = lock.upgradable_read();
+    let read_guard = lock.read();
+    let upgrade_guard = match RwLockUpgradableReadGuard::try_upgrade(upgrade_guard) {
+        Ok(_) => panic!("try_upgrade should fail while read_guard is in scope"),
+        Err(guard) => guard,
+    };
+    drop(read_guard);
+
+    let mut write_guard = RwLockUpgradableReadGuard::upgrade(upgrade_guard);
+    *write_guard = 1;
+    assert!(lock.try_read().is_none());
+
+    let read_guard = RwLockWriteGuard::downgrade(write_guard);
+    assert_eq!(*read_guard, 1);
+    assert_eq!(lock.try_read().as_deref(), Some(&1));
+    assert!(lock.try_write().is_none());
+    drop(read_guard);
+
+    let write_guard = lock.write();
+    let upgrade_guard = RwLockWriteGuard::downgrade_to_upgradable(write_guard);
+    assert!(lock.try_read().is_some());
+    assert!(lock.try_upgradable_read().is_none());
+    drop(upgrade_guard);
+
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn timed() {
+    let timeout = Duration::from_millis(50);
+    let lock = PFDistLock::new(0);
+
+    let read_guard = lock.read();
+    assert!(lock.try_write_for(timeout).is_none());
+    assert!(lock.try_read_for(timeout).is_some());
+    drop(read_guard);
+
+    let write_guard = lock.write();
+    assert!(lock.try_read_for(timeout).is_none());
+    assert!(lock.try_write_for(timeout).is_none());
+    drop(write_guard);
+
+    assert!(lock.try_write_for(timeout).is_some());
+}
+
+/// Readers that give up behind a queued writer must not count as departed
+/// readers, or the writer enters while the first reader still holds the lock.
+#[test]
+fn failed_readers_keep_writer_out() {
+    let lock = Arc::new(PFDistLock::new(()));
+    let read_guard = lock.read();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _w = lock.write();
+            tx.send(()).unwrap();
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_read_for(Duration::from_millis(10)).is_none());
+    thread::sleep(Duration::from_millis(50));
+    assert!(rx.try_recv().is_err(), "writer entered during a read phase");
+
+    drop(read_guard);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    writer.join().unwrap();
+}
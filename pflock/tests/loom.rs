@@ -0,0 +1,353 @@
+//! Model-checked tests of `RawPFLock` and `RawPFDistLock`: loom runs every
+//! interleaving (up to a preemption bound) of a few readers and writers under
+//! the C11 memory model and fails on any data race on the protected value.
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+#![cfg(loom)]
+
+use lock_api::{RawRwLock, RawRwLockDowngrade, RawRwLockUpgrade};
+use loom::cell::UnsafeCell;
+use loom::sync::Arc;
+use loom::thread;
+use pflock::{RawPFDistLock, RawPFLock};
+
+/// A value protected by a raw lock. Accesses go through loom's `UnsafeCell`,
+/// which checks that they are ordered by the lock's happens-before edges.
+struct Locked<R = RawPFLock> {
+    lock: R,
+    value: UnsafeCell<usize>,
+}
+
+unsafe impl<R: Sync> Sync for Locked<R> {}
+
+impl Locked {
+    fn new() -> Arc<Self> {
+        Locked::with_lock(RawPFLock::new())
+    }
+}
+
+impl<R: RawRwLock> Locked<R> {
+    fn with_lock(lock: R) -> Arc<Self> {
+        Arc::new(Locked {
+            lock,
+            value: UnsafeCell::new(0),
+        })
+    }
+
+    fn get(&self) -> usize {
+        self.value.with(|v| unsafe { *v })
+    }
+
+    fn increment(&self) {
+        self.value.with_mut(|v| unsafe { *v += 1 });
+    }
+
+    fn read(&self) -> usize {
+        self.lock.lock_shared();
+        let value = self.get();
+        unsafe { self.lock.unlock_shared() };
+        value
+    }
+
+    fn write(&self) {
+        self.lock.lock_exclusive();
+        self.increment();
+        unsafe { self.lock.unlock_exclusive() };
+    }
+}
+
+fn model<F: Fn() + Sync + Send + 'static>(f: F) {
+    let mut builder = loom::model::Builder::new();
+    // Every spin is a branch, so spinning threads need more than the default
+    builder.max_branches = 100_000;
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(3);
+    }
+    builder.check(f);
+}
+
+#[test]
+fn writer_and_reader() {
+    model(|| {
+        let data = Locked::new();
+
+        let writer = {
+            let data = data.clone();
+            thread::spawn(move || data.write())
+        };
+        let value = data.read();
+        writer.join().unwrap();
+
+        assert!(value <= 1);
+        assert_eq!(data.read(), 1);
+    });
+}
+
+#[test]
+fn two_writers() {
+    model(|| {
+        let data = Locked::new();
+
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let data = data.clone();
+                thread::spawn(move || data.write())
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(data.read(), 2);
+    });
+}
+
+/// Two threads that each read and write, so a writer phase follows a reader
+/// phase and the other way around.
+#[test]
+fn alternating_phases() {
+    model(|| {
+        let data = Locked::new();
+
+        let other = {
+            let data = data.clone();
+            thread::spawn(move || {
+                let value = data.read();
+                data.write();
+                value
+            })
+        };
+        data.write();
+        let value = data.read();
+
+        assert!(other.join().unwrap() <= 1);
+        assert!(value >= 1);
+        assert_eq!(data.read(), 2);
+    });
+}
+
+/// A try-reader that gives up during a writer phase must not let the writer
+/// in while an earlier reader still holds the lock.
+#[test]
+fn failed_try_read_during_writer_phase() {
+    model(|| {
+        let data = Locked::new();
+
+        data.lock.lock_shared();
+        let writer = {
+            let data = data.clone();
+            thread::spawn(move || data.write())
+        };
+        let try_reader = {
+            let data = data.clone();
+            thread::spawn(move || {
+                if data.lock.try_lock_shared() {
+                    data.get();
+                    unsafe { data.lock.unlock_shared() };
+                }
+            })
+        };
+
+        // Still protected from the writer
+        data.get();
+        try_reader.join().unwrap();
+        data.get();
+        unsafe { data.lock.unlock_shared() };
+
+        writer.join().unwrap();
+        assert_eq!(data.read(), 1);
+    });
+}
+
+#[test]
+fn try_write_and_reader() {
+    model(|| {
+        let data = Locked::new();
+
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read())
+        };
+        if data.lock.try_lock_exclusive() {
+            data.increment();
+            unsafe { data.lock.unlock_exclusive() };
+        }
+        let value = reader.join().unwrap();
+
+        assert!(value <= data.read());
+    });
+}
+
+#[test]
+fn upgrade_with_reader() {
+    model(|| {
+        let data = Locked::new();
+
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read())
+        };
+
+        data.lock.lock_upgradable();
+        let before = data.get();
+        unsafe { data.lock.upgrade() };
+        data.increment();
+        unsafe { data.lock.downgrade() };
+        assert_eq!(data.get(), before + 1);
+        unsafe { data.lock.unlock_shared() };
+
+        assert!(reader.join().unwrap() <= 1);
+    });
+}
+
+#[test]
+fn upgradable_and_writer() {
+    model(|| {
+        let data = Locked::new();
+
+        let writer = {
+            let data = data.clone();
+            thread::spawn(move || data.write())
+        };
+
+        data.lock.lock_upgradable();
+        let value = data.get();
+        unsafe { data.lock.unlock_upgradable() };
+        writer.join().unwrap();
+
+        assert!(value <= 1);
+        assert_eq!(data.read(), 1);
+    });
+}
+
+/// A `try_lock_exclusive` that fails because of a held read lock, followed by
+/// a blocking writer. A reader arriving during the attempt must not be left
+/// waiting on writer bits of the failed attempt that the next writer sets
+/// again.
+#[test]
+fn failed_try_write_then_write() {
+    model(|| {
+        let data = Locked::new();
+
+        data.lock.lock_shared();
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read())
+        };
+
+        assert!(!data.lock.try_lock_exclusive());
+        unsafe { data.lock.unlock_shared() };
+        data.write();
+
+        assert!(reader.join().unwrap() <= 1);
+        assert_eq!(data.read(), 1);
+    });
+}
+
+/// A failed `try_upgrade` followed by a blocking `upgrade` on the same writer
+/// ticket. The reader's first read makes the attempt fail; its second read
+/// may arrive during it, and must not be left waiting on writer bits that
+/// the `upgrade` sets again.
+#[test]
+fn failed_try_upgrade_then_upgrade() {
+    model(|| {
+        let data = Locked::new();
+
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read().max(data.read()))
+        };
+
+        data.lock.lock_upgradable();
+        unsafe {
+            if !data.lock.try_upgrade() {
+                data.lock.upgrade();
+            }
+        }
+        data.increment();
+        unsafe { data.lock.unlock_exclusive() };
+
+        assert!(reader.join().unwrap() <= 1);
+        assert_eq!(data.read(), 1);
+    });
+}
+
+/// The same for `RawPFDistLock`, with the reader in the other slot.
+#[test]
+fn dist_failed_try_upgrade_then_upgrade() {
+    model(|| {
+        let data = Locked::with_lock(RawPFDistLock::<2>::new());
+
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read().max(data.read()))
+        };
+
+        data.lock.lock_upgradable();
+        unsafe {
+            if !data.lock.try_upgrade() {
+                data.lock.upgrade();
+            }
+        }
+        data.increment();
+        unsafe { data.lock.unlock_exclusive() };
+
+        assert!(reader.join().unwrap() <= 1);
+        assert_eq!(data.read(), 1);
+    });
+}
+
+/// A reader that arrives during a writer phase, followed by an upgradable
+/// lock that takes and returns a writer ticket without a writer phase, and
+/// another writer. The reader must see the first phase end even if it only
+/// looks again once the second writer has set its bits.
+#[test]
+fn upgradable_between_writer_phases() {
+    model(|| {
+        let data = Locked::new();
+
+        data.lock.lock_exclusive();
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read())
+        };
+        data.increment();
+        unsafe { data.lock.unlock_exclusive() };
+
+        data.lock.lock_upgradable();
+        unsafe { data.lock.unlock_upgradable() };
+        data.write();
+
+        assert!(reader.join().unwrap() >= 1);
+        assert_eq!(data.read(), 2);
+    });
+}
+
+/// A writer phase, then a failed `try_upgrade` and a blocking `upgrade` on
+/// `RawPFDistLock`. The reader's second read may arrive during any of them.
+#[test]
+fn dist_writer_then_failed_try_upgrade() {
+    model(|| {
+        let data = Locked::with_lock(RawPFDistLock::<2>::new());
+
+        let reader = {
+            let data = data.clone();
+            thread::spawn(move || data.read().max(data.read()))
+        };
+
+        data.write();
+        data.lock.lock_upgradable();
+        unsafe {
+            if !data.lock.try_upgrade() {
+                data.lock.upgrade();
+            }
+        }
+        data.increment();
+        unsafe { data.lock.unlock_exclusive() };
+
+        assert!(reader.join().unwrap() <= 2);
+        assert_eq!(data.read(), 2);
+    });
+}
@@ -0,0 +1,101 @@
+use pflock::multi::{self, Mode};
+use pflock::PFLock;
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn smoke() {
+    let a = PFLock::new(1);
+    let b = PFLock::new(2);
+    {
+        let mut guard = multi::lock(&[multi::read(&a), multi::write(&b)]);
+        assert_eq!(guard.mode(&a), Some(Mode::Read));
+        assert_eq!(guard.mode(&b), Some(Mode::Write));
+        *guard.get_mut(&b) += *guard.get(&a);
+
+        assert!(a.try_read().is_some());
+        assert!(a.try_write().is_none());
+        assert!(b.try_read().is_none());
+    }
+    assert!(a.try_write().is_some());
+    assert_eq!(*b.try_write().unwrap(), 3);
+}
+
+#[test]
+fn duplicates_are_merged() {
+    let a = PFLock::new(());
+    let guard = multi::lock(&[multi::read(&a), multi::write(&a), multi::read(&a)]);
+    assert_eq!(guard.mode(&a), Some(Mode::Write));
+    assert!(a.try_read().is_none());
+    drop(guard);
+
+    let guard = multi::lock(&[multi::read(&a), multi::read(&a)]);
+    assert_eq!(guard.mode(&a), Some(Mode::Read));
+    drop(guard);
+    assert!(a.try_write().is_some());
+}
+
+#[test]
+fn try_lock_takes_all_or_nothing() {
+    let a = PFLock::new(());
+    let b = PFLock::new(());
+    let write_guard = b.write();
+
+    assert!(multi::try_lock(&[multi::write(&a), multi::read(&b)]).is_none());
+    assert!(a.try_write().is_some());
+
+    drop(write_guard);
+    let guard = multi::try_lock(&[multi::write(&a), multi::read(&b)]).unwrap();
+    assert!(a.try_read().is_none());
+    assert!(b.try_read().is_some());
+    drop(guard);
+}
+
+#[test]
+fn opposite_orders_do_not_deadlock() {
+    const N: usize = 1000;
+
+    let locks = Arc::new((PFLock::new(0), PFLock::new(0)));
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let locks = locks.clone();
+            thread::spawn(move || {
+                let (a, b) = (&locks.0, &locks.1);
+                for _ in 0..N {
+                    let requests = if i % 2 == 0 {
+                        [multi::write(a), multi::write(b)]
+                    } else {
+                        [multi::write(b), multi::write(a)]
+                    };
+                    let mut guard = multi::lock(&requests);
+                    *guard.get_mut(a) += 1;
+                    *guard.get_mut(b) += 1;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(*locks.0.read(), 4 * N);
+    assert_eq!(*locks.1.read(), 4 * N);
+}
+
+#[test]
+#[should_panic(expected = "not held")]
+fn get_unheld_lock() {
+    let a = PFLock::new(());
+    let b = PFLock::new(());
+    let guard = multi::lock(&[multi::read(&a)]);
+    guard.get(&b);
+}
+
+#[test]
+#[should_panic(expected = "not held for writing")]
+fn get_mut_read_lock() {
+    let a = PFLock::new(());
+    let mut guard = multi::lock(&[multi::read(&a)]);
+    guard.get_mut(&a);
+}
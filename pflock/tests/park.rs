@@ -0,0 +1,236 @@
+//! Tests for the parking phase-fair lock, mostly the ones in `ported.rs` run
+//! against `PFParkLock`.
+
+use pflock::PFParkLock;
+use rand::Rng;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn smoke() {
+    let l = PFParkLock::new(());
+    drop(l.read());
+    drop(l.write());
+    drop((l.read(), l.read()));
+    drop(l.write());
+}
+
+#[test]
+fn frob() {
+    const N: u32 = 10;
+    const M: u32 = 1000;
+
+    let r = Arc::new(PFParkLock::new(()));
+
+    let (tx, rx) = channel::<()>();
+    for _ in 0..N {
+        let tx = tx.clone();
+        let r = r.clone();
+        thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            for _ in 0..M {
+                if rng.gen_bool(1.0 / N as f64) {
+                    drop(r.write());
+                } else {
+                    drop(r.read());
+                }
+            }
+            drop(tx);
+        });
+    }
+    drop(tx);
+    let _ = rx.recv();
+}
+
+#[test]
+fn test_rw_arc() {
+    let arc = Arc::new(PFParkLock::new(0));
+    let arc2 = arc.clone();
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        let mut lock = arc2.write();
+        for _ in 0..10 {
+            let tmp = *lock;
+            *lock = -1;
+            thread::yield_now();
+            *lock = tmp + 1;
+        }
+        tx.send(()).unwrap();
+    });
+
+    // Readers try to catch the writer in the act
+    let mut children = Vec::new();
+    for _ in 0..5 {
+        let arc3 = arc.clone();
+        children.push(thread::spawn(move || {
+            let lock = arc3.read();
+            assert!(*lock >= 0);
+        }));
+    }
+
+    // Wait for children to pass their asserts
+    for r in children {
+        assert!(r.join().is_ok());
+    }
+
+    // Wait for writer to finish
+    rx.recv().unwrap();
+    let lock = arc.read();
+    assert_eq!(*lock, 10);
+}
+
+#[test]
+fn test_rwlock_try_read() {
+    let lock = PFParkLock::new(0isize);
+    {
+        let read_guard = lock.read();
+
+        let read_result = lock.try_read();
+        assert!(
+            read_result.is_some(),
+            "try_read should succeed while read_guard is in scope"
+        );
+
+        drop(read_guard);
+    }
+    {
+        let write_guard = lock.write();
+
+        let read_result = lock.try_read();
+        assert!(
+            read_result.is_none(),
+            "try_read should fail while write_guard is in scope"
+        );
+
+        drop(write_guard);
+    }
+}
+
+#[test]
+fn test_rwlock_try_write() {
+    let lock = PFParkLock::new(0isize);
+    {
+        let read_guard = lock.read();
+
+        let write_result = lock.try_write();
+        assert!(
+            write_result.is_none(),
+            "try_write should fail while read_guard is in scope"
+        );
+
+        drop(read_guard);
+    }
+    {
+        let write_guard = lock.write();
+
+        let write_result = lock.try_write();
+        assert!(
+            write_result.is_none(),
+            "try_write should fail while write_guard is in scope"
+        );
+
+        drop(write_guard);
+    }
+
+    assert!(lock.try_write().is_some());
+}
+
+/// Long critical sections: writers are serialized and none of them is lost.
+#[test]
+fn long_critical_sections() {
+    const N: usize = 8;
+
+    let lock = Arc::new(PFParkLock::new(0));
+
+    let mut handles = vec![];
+    for i in 0..N {
+        let lock = lock.clone();
+        handles.push(thread::spawn(move || {
+            if i % 2 == 0 {
+                let mut w = lock.write();
+                let tmp = *w;
+                thread::sleep(Duration::from_millis(10));
+                *w = tmp + 1;
+            } else {
+                let r = lock.read();
+                thread::sleep(Duration::from_millis(10));
+                assert!(*r <= N / 2);
+            }
+        }));
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(*lock.read(), N / 2);
+}
+
+/// All readers blocked by one writer phase are woken together: they hold the
+/// lock at the same time even though another writer is queued behind them.
+#[test]
+fn readers_woken_as_a_phase() {
+    const N: usize = 4;
+
+    let lock = Arc::new(PFParkLock::new(0));
+    let barrier = Arc::new(Barrier::new(N));
+
+    let write_guard = lock.write();
+
+    let mut readers = vec![];
+    for _ in 0..N {
+        let lock = lock.clone();
+        let barrier = barrier.clone();
+        readers.push(thread::spawn(move || {
+            let r = lock.read();
+            // Only passes if all N readers are inside at once
+            barrier.wait();
+            *r
+        }));
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            *lock.write() = 2;
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    drop(write_guard);
+    for reader in readers {
+        assert_eq!(reader.join().unwrap(), 0);
+    }
+    writer.join().unwrap();
+    assert_eq!(*lock.read(), 2);
+}
+
+/// A failed `try_read` behind a queued writer must not count as a departed
+/// reader, or the writer enters while the first reader still holds the lock.
+#[test]
+fn failed_try_read_keeps_writer_out() {
+    let lock = Arc::new(PFParkLock::new(()));
+    let read_guard = lock.read();
+
+    let (tx, rx) = channel();
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _w = lock.write();
+            tx.send(()).unwrap();
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    assert!(lock.try_read().is_none());
+    thread::sleep(Duration::from_millis(50));
+    assert!(rx.try_recv().is_err(), "writer entered during a read phase");
+
+    drop(read_guard);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    writer.join().unwrap();
+}
@@ -1,6 +1,6 @@
 //! Tests ported from https://github.com/Amanieu/parking_lot/blob/master/src/rwlock.rs
 
-use pflock::PFLock;
+use pflock::{PFLock, PFLockWriteGuard};
 use rand::Rng;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::channel;
@@ -284,6 +284,28 @@ fn test_clone() {
     assert_eq!(Arc::strong_count(&b), 2);
 }
 
+#[test]
+fn test_rwlock_downgrade() {
+    let x = Arc::new(PFLock::new(0));
+    let mut handles = Vec::new();
+    for _ in 0..8 {
+        let x = x.clone();
+        handles.push(thread::spawn(move || {
+            for _ in 0..100 {
+                let mut writer = x.write();
+                *writer += 1;
+                let cur_val = *writer;
+                let reader = PFLockWriteGuard::downgrade(writer);
+                assert_eq!(cur_val, *reader);
+            }
+        }));
+    }
+    for handle in handles {
+        handle.join().unwrap()
+    }
+    assert_eq!(*x.read(), 800);
+}
+
 #[cfg(feature = "serde")]
 #[test]
 fn test_serde() {
@@ -297,6 +319,12 @@ fn test_serde() {
     assert_eq!(contents, *(deserialized.read()));
 }
 
+#[test]
+fn test_rwlock_default() {
+    let lock: PFLock<Vec<u8>> = Default::default();
+    assert!(lock.read().is_empty());
+}
+
 #[test]
 fn test_issue_203() {
     struct Bar(PFLock<()>);
@@ -308,6 +336,8 @@ fn test_issue_203() {
     }
 
     thread_local! {
+        // `RwLock::new` is only a `const fn` in newer lock_api releases
+        #[allow(clippy::missing_const_for_thread_local)]
         static B: Bar = Bar(PFLock::new(()));
     }
 
@@ -0,0 +1,162 @@
+//! Tests for the hooks of `RawPFPreemptLock` and the Linux priority ceiling.
+
+use pflock::{PFPreemptLock, PreemptionControl};
+use std::cell::Cell;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static ENTERED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the hook calls on the current thread.
+struct Counting;
+
+impl PreemptionControl for Counting {
+    const INIT: Self = Counting;
+
+    fn enter(&self) {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        ENTERED.with(|entered| entered.set(entered.get() + 1));
+    }
+
+    fn exit(&self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+fn depth() -> usize {
+    DEPTH.with(Cell::get)
+}
+
+fn entered() -> usize {
+    ENTERED.with(Cell::get)
+}
+
+#[test]
+fn hooks_bracket_critical_sections() {
+    let lock = PFPreemptLock::<Counting, _>::new(0);
+
+    let (r1, r2) = (lock.read(), lock.read());
+    assert_eq!(depth(), 2);
+    drop((r1, r2));
+    assert_eq!(depth(), 0);
+
+    *lock.write() += 1;
+    assert_eq!(depth(), 0);
+    assert_eq!(entered(), 3);
+}
+
+#[test]
+fn failed_try_lock_exits() {
+    let lock = PFPreemptLock::<Counting, _>::new(0);
+
+    let write_guard = lock.write();
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_write().is_none());
+    assert_eq!(depth(), 1);
+    assert_eq!(entered(), 3);
+    drop(write_guard);
+
+    let read_guard = lock.try_read().unwrap();
+    assert_eq!(depth(), 1);
+    drop(read_guard);
+    assert_eq!(depth(), 0);
+}
+
+#[cfg(target_os = "linux")]
+mod ceiling {
+    use pflock::{PFPreemptLock, PriorityCeiling, RawPFPreemptLock};
+    use std::mem;
+    use std::thread;
+
+    fn sched() -> (i32, i32) {
+        let mut policy = 0;
+        let mut param: libc::sched_param = unsafe { mem::zeroed() };
+        let err =
+            unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) };
+        assert_eq!(err, 0);
+        (policy, param.sched_priority)
+    }
+
+    /// Whether this thread may switch to SCHED_FIFO, which needs
+    /// `CAP_SYS_NICE` or an `RLIMIT_RTPRIO`.
+    fn can_use_fifo() -> bool {
+        let base = sched();
+        let mut param: libc::sched_param = unsafe { mem::zeroed() };
+        param.sched_priority = 1;
+        let self_ = unsafe { libc::pthread_self() };
+        if unsafe { libc::pthread_setschedparam(self_, libc::SCHED_FIFO, &param) } != 0 {
+            return false;
+        }
+        param.sched_priority = base.1;
+        assert_eq!(
+            unsafe { libc::pthread_setschedparam(self_, base.0, &param) },
+            0
+        );
+        true
+    }
+
+    fn ceiling_lock<const PRIORITY: i32>(
+        ceiling: PriorityCeiling<PRIORITY>,
+    ) -> PFPreemptLock<PriorityCeiling<PRIORITY>, ()> {
+        PFPreemptLock::const_new(RawPFPreemptLock::with_control(ceiling), ())
+    }
+
+    #[test]
+    fn new_checks_privilege() {
+        thread::spawn(|| {
+            let base = sched();
+            assert_eq!(PriorityCeiling::<10>::new().is_ok(), can_use_fifo());
+            assert_eq!(sched(), base);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn ceiling_is_applied_and_restored() {
+        // Run on a fresh thread, so the test thread's scheduling is left alone
+        thread::spawn(|| {
+            if !can_use_fifo() {
+                eprintln!("skipping: SCHED_FIFO not permitted");
+                return;
+            }
+            let base = sched();
+            let low = ceiling_lock(PriorityCeiling::<10>::new().unwrap());
+            let high = ceiling_lock(PriorityCeiling::<20>::new().unwrap());
+
+            let low_guard = low.read();
+            assert_eq!(sched(), (libc::SCHED_FIFO, 10));
+            let high_guard = high.write();
+            assert_eq!(sched(), (libc::SCHED_FIFO, 20));
+
+            // Released out of order, the thread keeps the higher ceiling
+            drop(low_guard);
+            assert_eq!(sched(), (libc::SCHED_FIFO, 20));
+            drop(high_guard);
+            assert_eq!(sched(), base);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn failed_try_lock_restores_priority() {
+        thread::spawn(|| {
+            if !can_use_fifo() {
+                eprintln!("skipping: SCHED_FIFO not permitted");
+                return;
+            }
+            let base = sched();
+            let lock = ceiling_lock(PriorityCeiling::<10>::new().unwrap());
+
+            let write_guard = lock.write();
+            assert!(lock.try_read().is_none());
+            assert_eq!(sched(), (libc::SCHED_FIFO, 10));
+            drop(write_guard);
+            assert_eq!(sched(), base);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -1,14 +1,18 @@
-#![feature(div_duration)]
+//! Read and write parallelism of each reader-writer lock in the crate.
+// The threads keep the `let _ =` of the original `PFLock` test
+#![allow(clippy::let_underscore_lock)]
 
-use pflock::PFLock;
+use lock_api::{RawRwLock, RwLock};
+use pflock::{RawPFDistLock, RawPFLock, RawRPLock, RawTFLock, RawWPLock};
+use std::any::type_name;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
 const NUM_ITERATIONS: usize = 40;
 
-fn read_serial(sleep_duration: Duration) -> Duration {
-    let lock = Arc::new(PFLock::new(0));
+fn read_serial<R: RawRwLock + Send + Sync + 'static>(sleep_duration: Duration) -> Duration {
+    let lock = Arc::new(RwLock::<R, _>::new(0));
 
     let now = Instant::now();
     for _ in 0..NUM_ITERATIONS {
@@ -24,8 +28,8 @@ fn read_serial(sleep_duration: Duration) -> Duration {
     now.elapsed()
 }
 
-fn read_parallel(sleep_duration: Duration) -> Duration {
-    let lock = Arc::new(PFLock::new(0));
+fn read_parallel<R: RawRwLock + Send + Sync + 'static>(sleep_duration: Duration) -> Duration {
+    let lock = Arc::new(RwLock::<R, _>::new(0));
 
     let mut threads = vec![];
 
@@ -46,8 +50,8 @@ fn read_parallel(sleep_duration: Duration) -> Duration {
     now.elapsed()
 }
 
-fn write_serial(sleep_duration: Duration) -> Duration {
-    let lock = Arc::new(PFLock::new(0));
+fn write_serial<R: RawRwLock + Send + Sync + 'static>(sleep_duration: Duration) -> Duration {
+    let lock = Arc::new(RwLock::<R, _>::new(0));
 
     let now = Instant::now();
     for _ in 0..NUM_ITERATIONS {
@@ -63,8 +67,8 @@ fn write_serial(sleep_duration: Duration) -> Duration {
     now.elapsed()
 }
 
-fn write_parallel(sleep_duration: Duration) -> Duration {
-    let lock = Arc::new(PFLock::new(0));
+fn write_parallel<R: RawRwLock + Send + Sync + 'static>(sleep_duration: Duration) -> Duration {
+    let lock = Arc::new(RwLock::<R, _>::new(0));
 
     let mut threads = vec![];
 
@@ -85,18 +89,19 @@ fn write_parallel(sleep_duration: Duration) -> Duration {
     now.elapsed()
 }
 
-#[test]
-fn read_write() {
+fn read_write<R: RawRwLock + Send + Sync + 'static>() {
     let sleep_duration = Duration::from_millis(50);
     println!(
-        "sleep {:?} in each thread, {} iterations",
-        sleep_duration, NUM_ITERATIONS
+        "{}: sleep {:?} in each thread, {} iterations",
+        type_name::<R>(),
+        sleep_duration,
+        NUM_ITERATIONS
     );
 
-    let rs = read_serial(sleep_duration);
+    let rs = read_serial::<R>(sleep_duration);
     println!("read serial    = {:?}", rs);
 
-    let rp = read_parallel(sleep_duration);
+    let rp = read_parallel::<R>(sleep_duration);
     println!("read parallel  = {:?}", rp);
 
     println!(
@@ -105,10 +110,10 @@ fn read_write() {
         rs.div_duration_f64(rp)
     );
 
-    let ws = write_serial(sleep_duration);
+    let ws = write_serial::<R>(sleep_duration);
     println!("write serial   = {:?}", ws);
 
-    let wp = write_parallel(sleep_duration);
+    let wp = write_parallel::<R>(sleep_duration);
     println!("write parallel = {:?}", wp);
 
     if wp > ws {
@@ -125,3 +130,28 @@ fn read_write() {
         );
     }
 }
+
+#[test]
+fn read_write_phase_fair() {
+    read_write::<RawPFLock>();
+}
+
+#[test]
+fn read_write_phase_fair_distributed() {
+    read_write::<RawPFDistLock>();
+}
+
+#[test]
+fn read_write_task_fair() {
+    read_write::<RawTFLock>();
+}
+
+#[test]
+fn read_write_reader_preference() {
+    read_write::<RawRPLock>();
+}
+
+#[test]
+fn read_write_writer_preference() {
+    read_write::<RawWPLock>();
+}
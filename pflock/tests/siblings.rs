@@ -0,0 +1,183 @@
+//! Tests run against every reader-writer lock in the crate, plus the blocking
+//! behaviour that tells the task-fair, reader-preference, writer-preference and
+//! phase-fair locks apart.
+
+use lock_api::{RawRwLock, RwLock};
+use pflock::{RawPFDistLock, RawPFLock, RawPFPreemptLock, RawRPLock, RawTFLock, RawWPLock};
+use rand::Rng;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const WAIT: Duration = Duration::from_millis(100);
+
+fn smoke<R: RawRwLock>() {
+    let l = RwLock::<R, _>::new(());
+    drop(l.read());
+    drop(l.write());
+    drop((l.read(), l.read()));
+    drop(l.write());
+}
+
+fn frob<R: RawRwLock + Send + Sync + 'static>() {
+    const N: u32 = 10;
+    const M: u32 = 1000;
+
+    let r = Arc::new(RwLock::<R, _>::new(0u32));
+
+    let mut handles = vec![];
+    for _ in 0..N {
+        let r = r.clone();
+        handles.push(thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            let mut writes = 0;
+            for _ in 0..M {
+                if rng.gen_bool(1.0 / N as f64) {
+                    *r.write() += 1;
+                    writes += 1;
+                } else {
+                    drop(r.read());
+                }
+            }
+            writes
+        }));
+    }
+
+    let writes: u32 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(*r.read(), writes);
+}
+
+fn try_read_write<R: RawRwLock>() {
+    let lock = RwLock::<R, _>::new(0isize);
+    {
+        let read_guard = lock.read();
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(read_guard);
+    }
+    {
+        let write_guard = lock.write();
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(write_guard);
+    }
+    assert!(lock.try_write().is_some());
+}
+
+/// Spawn a thread that takes a read (or write) lock and reports when it has it,
+/// with how many lockers counted in the lock's data got it before.
+fn spawn_locker<R: RawRwLock + Send + Sync + 'static>(
+    lock: &Arc<RwLock<R, AtomicUsize>>,
+    write: bool,
+) -> Receiver<usize> {
+    let (tx, rx) = channel();
+    let lock = lock.clone();
+    thread::spawn(move || {
+        if write {
+            let guard = lock.write();
+            tx.send(guard.fetch_add(1, Ordering::SeqCst)).unwrap();
+        } else {
+            let guard = lock.read();
+            tx.send(guard.fetch_add(1, Ordering::SeqCst)).unwrap();
+        }
+    });
+    thread::sleep(WAIT);
+    rx
+}
+
+/// A reader holds the lock and a writer is waiting for it. Returns whether a
+/// second reader can get in before the writer.
+fn reader_overtakes_waiting_writer<R: RawRwLock + Send + Sync + 'static>() -> bool {
+    let lock = Arc::new(RwLock::<R, _>::new(AtomicUsize::new(0)));
+
+    let read_guard = lock.read();
+    let writer = spawn_locker(&lock, true);
+    let reader = spawn_locker(&lock, false);
+
+    let overtook = reader.try_recv().is_ok();
+    drop(read_guard);
+    writer.recv_timeout(Duration::from_secs(5)).unwrap();
+    if !overtook {
+        reader.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    overtook
+}
+
+/// A writer holds the lock, then a reader and a second writer queue up in that
+/// order. Returns whether the reader gets in before the second writer.
+fn reader_before_later_writer<R: RawRwLock + Send + Sync + 'static>() -> bool {
+    let lock = Arc::new(RwLock::<R, _>::new(AtomicUsize::new(0)));
+
+    let write_guard = lock.write();
+    let reader = spawn_locker(&lock, false);
+    let writer = spawn_locker(&lock, true);
+
+    drop(write_guard);
+    let reader = reader.recv_timeout(Duration::from_secs(5)).unwrap();
+    let writer = writer.recv_timeout(Duration::from_secs(5)).unwrap();
+    reader < writer
+}
+
+macro_rules! lock_tests {
+    ($name:ident, $raw:ty) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn smoke() {
+                super::smoke::<$raw>();
+            }
+
+            #[test]
+            fn frob() {
+                super::frob::<$raw>();
+            }
+
+            #[test]
+            fn try_read_write() {
+                super::try_read_write::<$raw>();
+            }
+        }
+    };
+}
+
+lock_tests!(phase_fair, RawPFLock);
+lock_tests!(phase_fair_distributed, RawPFDistLock);
+lock_tests!(phase_fair_preempt, RawPFPreemptLock);
+lock_tests!(task_fair, RawTFLock);
+lock_tests!(reader_preference, RawRPLock);
+lock_tests!(writer_preference, RawWPLock);
+
+#[test]
+fn phase_fair_blocking() {
+    // Readers wait for at most one writer phase: the queued reader goes first
+    assert!(!reader_overtakes_waiting_writer::<RawPFLock>());
+    assert!(reader_before_later_writer::<RawPFLock>());
+}
+
+#[test]
+fn phase_fair_distributed_blocking() {
+    assert!(!reader_overtakes_waiting_writer::<RawPFDistLock>());
+    assert!(reader_before_later_writer::<RawPFDistLock>());
+}
+
+#[test]
+fn task_fair_blocking() {
+    // Strict FIFO order
+    assert!(!reader_overtakes_waiting_writer::<RawTFLock>());
+    assert!(reader_before_later_writer::<RawTFLock>());
+}
+
+#[test]
+fn reader_preference_blocking() {
+    assert!(reader_overtakes_waiting_writer::<RawRPLock>());
+    assert!(reader_before_later_writer::<RawRPLock>());
+}
+
+#[test]
+fn writer_preference_blocking() {
+    assert!(!reader_overtakes_waiting_writer::<RawWPLock>());
+    assert!(!reader_before_later_writer::<RawWPLock>());
+}
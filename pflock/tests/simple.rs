@@ -1,4 +1,6 @@
-use pflock::PFLock;
+#![allow(clippy::should_implement_trait, clippy::mut_from_ref, clippy::let_unit_value)]
+
+use pflock::{PFLock, RawPFLock};
 use std::cell::{Cell, UnsafeCell};
 use std::sync::Arc;
 use std::thread;
@@ -106,3 +108,36 @@ fn cell() {
 
     assert_eq!((num_threads * num_repeats) / 2, lock.read().get());
 }
+
+#[test]
+fn raw_static() {
+    static LOCK: RawPFLock = RawPFLock::new();
+    static COUNT: MockCell<usize> = MockCell(Cell::new(0));
+
+    let num_threads = 3;
+    let num_repeats = 1000;
+
+    let mut handles = vec![];
+
+    for _ in 0..num_threads {
+        handles.push(thread::spawn(move || {
+            for i in 0..num_repeats {
+                if i % 2 == 0 {
+                    LOCK.write_lock();
+                    COUNT.set(COUNT.get() + 1);
+                    unsafe { LOCK.write_unlock() };
+                } else {
+                    LOCK.read_lock();
+                    let _ = COUNT.get();
+                    unsafe { LOCK.read_unlock() };
+                }
+            }
+        }));
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!((num_threads * num_repeats) / 2, COUNT.get());
+}
@@ -0,0 +1,122 @@
+//! Tests for the blocking statistics of the `stats` feature
+#![cfg(feature = "stats")]
+
+use pflock::stats::SpinStats;
+use pflock::{PFLock, PFLockExt};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const HOLD: Duration = Duration::from_millis(100);
+
+#[test]
+fn counts_acquisitions() {
+    let lock = PFLock::new(0);
+    for _ in 0..3 {
+        drop(lock.read());
+    }
+    *lock.write() += 1;
+
+    let stats = lock.stats();
+    assert_eq!(stats.read.count, 3);
+    assert_eq!(stats.write.count, 1);
+    assert_eq!(stats.read.histogram.iter().sum::<u64>(), 3);
+    assert_eq!(stats.max_reader_phases, 0);
+    assert_eq!(stats.max_writer_queue, 0);
+
+    lock.reset_stats();
+    assert_eq!(lock.stats(), Default::default());
+}
+
+#[test]
+fn bucket_ranges_are_contiguous() {
+    for i in 1..pflock::stats::BUCKETS {
+        assert_eq!(SpinStats::bucket_range(i - 1).1, SpinStats::bucket_range(i).0);
+    }
+}
+
+/// A reader that arrives while a writer holds the lock waits for that one
+/// writer phase, and no longer than its critical section (plus slack).
+#[test]
+fn reader_waits_for_one_phase() {
+    let lock = Arc::new(PFLock::new(()));
+
+    let write_guard = lock.write();
+    let (tx, rx) = channel();
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _guard = lock.read();
+            tx.send(()).unwrap();
+        })
+    };
+    thread::sleep(HOLD);
+    drop(write_guard);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    reader.join().unwrap();
+
+    let stats = lock.stats();
+    assert_eq!(stats.max_reader_phases, 1);
+    assert!(stats.read.max >= HOLD / 2);
+    assert!(stats.read.max < HOLD * 20);
+}
+
+#[test]
+fn records_writer_queue() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let write_guard = lock.write();
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let lock = lock.clone();
+            let writer = thread::spawn(move || *lock.write() += 1);
+            thread::sleep(HOLD);
+            writer
+        })
+        .collect();
+    drop(write_guard);
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let stats = lock.stats();
+    assert_eq!(*lock.read(), 2);
+    assert_eq!(stats.write.count, 3);
+    assert_eq!(stats.max_writer_queue, 2);
+    assert_eq!(stats.max_reader_phases, 0);
+}
+
+/// A reader that arrives behind a writer with more writers queued waits for
+/// the current writer phase only, not for the queued ones.
+#[test]
+fn reader_skips_queued_writers() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let mut write_guard = lock.write();
+    *write_guard += 1;
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let lock = lock.clone();
+            let writer = thread::spawn(move || *lock.write() += 1);
+            thread::sleep(HOLD);
+            writer
+        })
+        .collect();
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || *lock.read())
+    };
+    thread::sleep(HOLD);
+    drop(write_guard);
+    let seen = reader.join().unwrap();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    // The reader came in between the first writer and the queued ones
+    assert_eq!(seen, 1);
+    let stats = lock.stats();
+    assert_eq!(stats.read.count, 1);
+    assert_eq!(stats.max_reader_phases, 1);
+}
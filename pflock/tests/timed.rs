@@ -0,0 +1,182 @@
+//! Tests for deadline-bounded acquisition (`try_read_for`, `try_write_until`, ...)
+
+use pflock::PFLock;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+#[test]
+fn smoke() {
+    let l = PFLock::new(());
+    drop(l.try_read_for(TIMEOUT).unwrap());
+    drop(l.try_write_for(TIMEOUT).unwrap());
+    drop((
+        l.try_read_until(Instant::now() + TIMEOUT).unwrap(),
+        l.try_read_for(TIMEOUT).unwrap(),
+    ));
+    drop(l.try_write_until(Instant::now() + TIMEOUT).unwrap());
+}
+
+#[test]
+fn test_rwlock_try_read_for() {
+    let lock = PFLock::new(0isize);
+    {
+        let read_guard = lock.read();
+
+        let read_result = lock.try_read_for(TIMEOUT);
+        assert!(
+            read_result.is_some(),
+            "try_read_for should succeed while read_guard is in scope"
+        );
+
+        drop(read_guard);
+    }
+    {
+        let write_guard = lock.write();
+
+        let now = Instant::now();
+        let read_result = lock.try_read_for(TIMEOUT);
+        assert!(
+            read_result.is_none(),
+            "try_read_for should fail while write_guard is in scope"
+        );
+        assert!(now.elapsed() >= TIMEOUT);
+
+        drop(write_guard);
+    }
+
+    // The reader that gave up must not hold up the next writer
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn test_rwlock_try_write_for() {
+    let lock = PFLock::new(0isize);
+    {
+        let read_guard = lock.read();
+
+        let now = Instant::now();
+        let write_result = lock.try_write_for(TIMEOUT);
+        assert!(
+            write_result.is_none(),
+            "try_write_for should fail while read_guard is in scope"
+        );
+        assert!(now.elapsed() >= TIMEOUT);
+
+        // The writer that gave up must not block new readers
+        assert!(lock.try_read().is_some());
+
+        drop(read_guard);
+    }
+    {
+        let write_guard = lock.write();
+
+        let write_result = lock.try_write_until(Instant::now() + TIMEOUT);
+        assert!(
+            write_result.is_none(),
+            "try_write_until should fail while write_guard is in scope"
+        );
+
+        drop(write_guard);
+    }
+
+    assert!(lock.try_write().is_some());
+}
+
+/// A timed writer that gives up while other writers are queued must leave the
+/// writer queue as it found it.
+#[test]
+fn timed_writer_keeps_queue_consistent() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let write_guard = lock.write();
+
+    let (tx, rx) = channel();
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let mut w = lock.write();
+            *w += 1;
+            tx.send(()).unwrap();
+        })
+    };
+    thread::sleep(TIMEOUT);
+
+    assert!(lock.try_write_for(TIMEOUT).is_none());
+    assert!(lock.try_write().is_none());
+    assert!(rx.try_recv().is_err(), "queued writer entered too early");
+
+    // The queued writer is next, and the lock is free after it
+    drop(write_guard);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    writer.join().unwrap();
+
+    let w = lock.try_write_for(TIMEOUT);
+    assert_eq!(w.as_deref(), Some(&1));
+}
+
+/// Readers that arrive while a timed writer waits for the lock are not held
+/// up by it.
+#[test]
+fn timed_writer_releases_readers() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let read_guard = lock.read();
+
+    let (tx, rx) = channel();
+    let timed_writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let w = lock.try_write_for(Duration::from_millis(200));
+            tx.send(w.is_some()).unwrap();
+        })
+    };
+    thread::sleep(TIMEOUT);
+
+    // This reader arrives while the timed writer waits
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _guard = lock.read();
+        })
+    };
+
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(false));
+    timed_writer.join().unwrap();
+    reader.join().unwrap();
+
+    drop(read_guard);
+    assert!(lock.try_write().is_some());
+}
+
+/// A reader that times out behind a queued writer must not count as a
+/// departed reader of the phase before it, or the writer enters while an
+/// earlier reader still holds the lock.
+#[test]
+fn timed_out_reader_keeps_writer_out() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let read_guard = lock.read();
+
+    let (tx, rx) = channel();
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _w = lock.write();
+            tx.send(()).unwrap();
+        })
+    };
+    thread::sleep(TIMEOUT);
+
+    assert!(lock.try_read_for(TIMEOUT).is_none());
+    assert!(lock.try_read().is_none());
+    thread::sleep(TIMEOUT);
+    assert!(rx.try_recv().is_err(), "writer entered during a read phase");
+
+    drop(read_guard);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    writer.join().unwrap();
+}
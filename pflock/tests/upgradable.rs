@@ -0,0 +1,293 @@
+//! Upgradable read and downgrade tests, partly ported from
+//! https://github.com/Amanieu/parking_lot/blob/master/src/rwlock.rs
+
+use pflock::{PFLock, PFLockUpgradableReadGuard, PFLockWriteGuard};
+use rand::Rng;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn smoke() {
+    let l = PFLock::new(());
+    drop(l.read());
+    drop(l.upgradable_read());
+    drop((l.read(), l.upgradable_read()));
+    drop(l.write());
+    drop(PFLockUpgradableReadGuard::upgrade(l.upgradable_read()));
+    drop(l.read());
+}
+
+#[test]
+fn frob() {
+    const N: u32 = 10;
+    const M: u32 = 1000;
+
+    let r = Arc::new(PFLock::new(()));
+
+    let (tx, rx) = channel::<()>();
+    for _ in 0..N {
+        let tx = tx.clone();
+        let r = r.clone();
+        thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            for _ in 0..M {
+                if rng.gen_bool(1.0 / N as f64) {
+                    drop(r.write());
+                } else if rng.gen_bool(1.0 / N as f64) {
+                    drop(PFLockUpgradableReadGuard::upgrade(r.upgradable_read()));
+                } else if rng.gen_bool(1.0 / N as f64) {
+                    drop(r.upgradable_read());
+                } else {
+                    drop(r.read());
+                }
+            }
+            drop(tx);
+        });
+    }
+    drop(tx);
+    let _ = rx.recv();
+}
+
+#[test]
+fn test_rw_arc_upgrade() {
+    let arc = Arc::new(PFLock::new(0));
+    let mut children = Vec::new();
+
+    for _ in 0..10 {
+        let arc2 = arc.clone();
+        children.push(thread::spawn(move || {
+            for _ in 0..100 {
+                let lock = arc2.upgradable_read();
+                let tmp = *lock;
+                let mut lock = PFLockUpgradableReadGuard::upgrade(lock);
+                *lock = tmp + 1;
+            }
+        }));
+    }
+
+    for r in children {
+        assert!(r.join().is_ok());
+    }
+
+    assert_eq!(*arc.read(), 1000);
+}
+
+#[test]
+fn test_rwlock_try_upgradable_read() {
+    let lock = PFLock::new(0isize);
+    {
+        let read_guard = lock.read();
+
+        let upgrade_result = lock.try_upgradable_read();
+        assert!(
+            upgrade_result.is_some(),
+            "try_upgradable_read should succeed while read_guard is in scope"
+        );
+
+        drop(read_guard);
+    }
+    {
+        let upgrade_guard = lock.upgradable_read();
+
+        let upgrade_result = lock.try_upgradable_read();
+        assert!(
+            upgrade_result.is_none(),
+            "try_upgradable_read should fail while upgrade_guard is in scope"
+        );
+
+        let write_result = lock.try_write();
+        assert!(
+            write_result.is_none(),
+            "try_write should fail while upgrade_guard is in scope"
+        );
+
+        let read_result = lock.try_read();
+        assert!(
+            read_result.is_some(),
+            "try_read should succeed while upgrade_guard is in scope"
+        );
+
+        drop(read_result);
+        drop(upgrade_guard);
+    }
+    {
+        let write_guard = lock.write();
+
+        let upgrade_result = lock.try_upgradable_read();
+        assert!(
+            upgrade_result.is_none(),
+            "try_upgradable_read should fail while write_guard is in scope"
+        );
+
+        drop(write_guard);
+    }
+
+    // A failed attempt must not leave a writer ticket behind
+    assert!(lock.try_write().is_some());
+}
+
+#[test]
+fn test_rwlock_try_upgrade() {
+    let lock = PFLock::new(0isize);
+
+    let read_guard = lock.read();
+    let upgrade_guard = lock.upgradable_read();
+    let upgrade_guard = match PFLockUpgradableReadGuard::try_upgrade(upgrade_guard) {
+        Ok(_) => panic!("try_upgrade should fail while read_guard is in scope"),
+        Err(guard) => guard,
+    };
+    drop(read_guard);
+
+    // Readers are admitted again after the failed upgrade
+    drop(lock.try_read().unwrap());
+
+    let mut write_guard = match PFLockUpgradableReadGuard::try_upgrade(upgrade_guard) {
+        Ok(guard) => guard,
+        Err(_) => panic!("try_upgrade should succeed once the other readers are gone"),
+    };
+    *write_guard = 1;
+    drop(write_guard);
+
+    assert_eq!(*lock.read(), 1);
+}
+
+/// Readers are not blocked by an upgradable reader that has not upgraded yet,
+/// even if a writer is queued behind it.
+#[test]
+fn upgradable_does_not_block_readers() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let upgrade_guard = lock.upgradable_read();
+
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            *lock.write() += 1;
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    let (tx, rx) = channel();
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let r = lock.read();
+            tx.send(*r).unwrap();
+        })
+    };
+
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(0));
+
+    drop(upgrade_guard);
+    writer.join().unwrap();
+    reader.join().unwrap();
+    assert_eq!(*lock.read(), 1);
+}
+
+/// A reader that arrives while the upgrader is writing waits for at most that
+/// one writer phase, even though another writer is queued behind the upgrader.
+#[test]
+fn reader_blocked_for_at_most_one_phase() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let upgrade_guard = lock.upgradable_read();
+
+    // A writer that holds the lock until told to release it
+    let (release_tx, release_rx) = channel::<()>();
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let mut w = lock.write();
+            *w = 2;
+            release_rx.recv().unwrap();
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    let mut write_guard = PFLockUpgradableReadGuard::upgrade(upgrade_guard);
+    *write_guard = 1;
+
+    let (tx, rx) = channel();
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let r = lock.read();
+            tx.send(*r).unwrap();
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    assert!(rx.try_recv().is_err(), "reader entered a writer phase");
+
+    // End the upgrader's writer phase. The reader gets in before the queued
+    // writer, which has not been released yet.
+    drop(write_guard);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+
+    release_tx.send(()).unwrap();
+    writer.join().unwrap();
+    reader.join().unwrap();
+    assert_eq!(*lock.read(), 2);
+}
+
+#[test]
+fn test_rwlock_upgrade_downgrade() {
+    let lock = PFLock::new(0);
+
+    let upgrade_guard = lock.upgradable_read();
+    let mut write_guard = PFLockUpgradableReadGuard::upgrade(upgrade_guard);
+    *write_guard = 1;
+
+    let upgrade_guard = PFLockWriteGuard::downgrade_to_upgradable(write_guard);
+    assert_eq!(*upgrade_guard, 1);
+    assert!(lock.try_read().is_some());
+    assert!(lock.try_write().is_none());
+    assert!(lock.try_upgradable_read().is_none());
+
+    let read_guard = PFLockUpgradableReadGuard::downgrade(upgrade_guard);
+    assert_eq!(*read_guard, 1);
+    assert!(lock.try_upgradable_read().is_some());
+    assert!(lock.try_write().is_none());
+    drop(read_guard);
+
+    assert!(lock.try_write().is_some());
+}
+
+/// Readers blocked by a writer are let in when it downgrades, while a writer
+/// queued behind it keeps waiting for the downgraded read lock.
+#[test]
+fn downgrade_admits_blocked_readers() {
+    let lock = Arc::new(PFLock::new(0));
+
+    let mut write_guard = lock.write();
+    *write_guard = 1;
+
+    let (tx, rx) = channel();
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let r = lock.read();
+            tx.send(*r).unwrap();
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(rx.try_recv().is_err(), "reader entered a writer phase");
+
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            *lock.write() = 2;
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    let read_guard = PFLockWriteGuard::downgrade(write_guard);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+    assert_eq!(*read_guard, 1);
+
+    drop(read_guard);
+    writer.join().unwrap();
+    reader.join().unwrap();
+    assert_eq!(*lock.read(), 2);
+}
@@ -0,0 +1,418 @@
+//! Bounded collections to share between transactions.
+//!
+//! Each collection allocates all of its storage when it is created and never
+//! grows, so an operation inside a transaction neither allocates nor takes
+//! longer than the bound given in its documentation. That bounds the
+//! critical sections of the conflict-set lock that protects the collection.
+//! An operation on a full collection fails and hands the value back.
+//!
+//! The collections keep their contents in a single `TxPtr`, and every
+//! operation takes the [`TxToken`] of the calling transaction and borrows it
+//! with `borrow_in`/`borrow_mut_in`. A call outside a transaction does not
+//! compile, and one in a transaction that has ended panics. Create a
+//! collection with `new_in` to name its [`ConflictGroup`]; each call then
+//! also panics unless the transaction holds the group's lock, for writing
+//! unless the operation only reads. Lookups return copies rather than
+//! references, so the contents cannot change under a caller. The contents
+//! move between the threads that run the transactions, so they have to be
+//! `Send`.
+
+use crate::{ConflictGroup, TxPtr, TxToken};
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash};
+
+/// A FIFO queue of at most `capacity` values.
+///
+/// Every operation runs in constant time.
+///
+/// ```
+/// use txcell::{atomically, TxQueue, TxSet};
+///
+/// let queue = TxQueue::new(2);
+/// atomically(&TxSet::new().write(0), |tx| {
+///     queue.push(tx, 1).unwrap();
+///     queue.push(tx, 2).unwrap();
+///     assert_eq!(queue.push(tx, 3), Err(3));
+///     assert_eq!(queue.pop(tx), Some(1));
+/// });
+/// ```
+#[derive(Debug)]
+pub struct TxQueue<T> {
+    values: TxPtr<VecDeque<T>>,
+    capacity: usize,
+}
+
+impl<T: Send> TxQueue<T> {
+    /// An empty queue with room for `capacity` values.
+    #[track_caller]
+    pub fn new(capacity: usize) -> TxQueue<T> {
+        TxQueue {
+            values: TxPtr::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// An empty queue with room for `capacity` values, protected by the lock
+    /// of `group`.
+    #[track_caller]
+    pub fn new_in(group: ConflictGroup, capacity: usize) -> TxQueue<T> {
+        TxQueue {
+            values: TxPtr::new_in(group, VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Append `value` at the back, or return it if the queue is full.
+    pub fn push(&self, tx: &TxToken, value: T) -> Result<(), T> {
+        let mut values = self.values.borrow_mut_in(tx);
+        if values.len() == self.capacity {
+            return Err(value);
+        }
+        values.push_back(value);
+        Ok(())
+    }
+
+    /// Remove the value at the front.
+    pub fn pop(&self, tx: &TxToken) -> Option<T> {
+        self.values.borrow_mut_in(tx).pop_front()
+    }
+
+    /// A copy of the value at the front.
+    pub fn peek(&self, tx: &TxToken) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.values.borrow_in(tx).front().cloned()
+    }
+
+    /// How many values the queue holds.
+    pub fn len(&self, tx: &TxToken) -> usize {
+        self.values.borrow_in(tx).len()
+    }
+
+    /// Whether the queue holds no values.
+    pub fn is_empty(&self, tx: &TxToken) -> bool {
+        self.values.borrow_in(tx).is_empty()
+    }
+
+    /// Whether the queue holds `capacity` values.
+    pub fn is_full(&self, tx: &TxToken) -> bool {
+        self.values.borrow_in(tx).len() == self.capacity
+    }
+
+    /// How many values the queue holds at most.
+    ///
+    /// This needs no transaction, as the capacity never changes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// A vector of at most `capacity` values.
+///
+/// `insert` and `remove` shift the values after the index, at most
+/// `capacity - 1` of them. Every other operation runs in constant time.
+///
+/// ```
+/// use txcell::{atomically, TxSet, TxVec};
+///
+/// let v = TxVec::new(3);
+/// atomically(&TxSet::new().write(0), |tx| {
+///     v.push(tx, 'a').unwrap();
+///     v.push(tx, 'c').unwrap();
+///     v.insert(tx, 1, 'b').unwrap();
+///     assert_eq!(v.push(tx, 'd'), Err('d'));
+///     assert_eq!(v.get(tx, 1), Some('b'));
+/// });
+/// ```
+#[derive(Debug)]
+pub struct TxVec<T> {
+    values: TxPtr<Vec<T>>,
+    capacity: usize,
+}
+
+impl<T: Send> TxVec<T> {
+    /// An empty vector with room for `capacity` values.
+    #[track_caller]
+    pub fn new(capacity: usize) -> TxVec<T> {
+        TxVec {
+            values: TxPtr::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// An empty vector with room for `capacity` values, protected by the
+    /// lock of `group`.
+    #[track_caller]
+    pub fn new_in(group: ConflictGroup, capacity: usize) -> TxVec<T> {
+        TxVec {
+            values: TxPtr::new_in(group, Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Append `value`, or return it if the vector is full.
+    pub fn push(&self, tx: &TxToken, value: T) -> Result<(), T> {
+        let mut values = self.values.borrow_mut_in(tx);
+        if values.len() == self.capacity {
+            return Err(value);
+        }
+        values.push(value);
+        Ok(())
+    }
+
+    /// Remove the last value.
+    pub fn pop(&self, tx: &TxToken) -> Option<T> {
+        self.values.borrow_mut_in(tx).pop()
+    }
+
+    /// A copy of the value at `index`.
+    pub fn get(&self, tx: &TxToken, index: usize) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.values.borrow_in(tx).get(index).cloned()
+    }
+
+    /// Replace the value at `index`, returning the old one.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn set(&self, tx: &TxToken, index: usize, value: T) -> T {
+        std::mem::replace(&mut self.values.borrow_mut_in(tx)[index], value)
+    }
+
+    /// Insert `value` at `index`, shifting the values after it, or return
+    /// it if the vector is full.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is greater than the length.
+    pub fn insert(&self, tx: &TxToken, index: usize, value: T) -> Result<(), T> {
+        let mut values = self.values.borrow_mut_in(tx);
+        if values.len() == self.capacity {
+            return Err(value);
+        }
+        values.insert(index, value);
+        Ok(())
+    }
+
+    /// Remove the value at `index`, shifting the values after it.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn remove(&self, tx: &TxToken, index: usize) -> T {
+        self.values.borrow_mut_in(tx).remove(index)
+    }
+
+    /// Remove the value at `index` and put the last value in its place.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn swap_remove(&self, tx: &TxToken, index: usize) -> T {
+        self.values.borrow_mut_in(tx).swap_remove(index)
+    }
+
+    /// How many values the vector holds.
+    pub fn len(&self, tx: &TxToken) -> usize {
+        self.values.borrow_in(tx).len()
+    }
+
+    /// Whether the vector holds no values.
+    pub fn is_empty(&self, tx: &TxToken) -> bool {
+        self.values.borrow_in(tx).is_empty()
+    }
+
+    /// Whether the vector holds `capacity` values.
+    pub fn is_full(&self, tx: &TxToken) -> bool {
+        self.values.borrow_in(tx).len() == self.capacity
+    }
+
+    /// How many values the vector holds at most.
+    ///
+    /// This needs no transaction, as the capacity never changes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// The slots of a `TxHashMap` and how many are used.
+#[derive(Debug)]
+struct Table<K, V> {
+    slots: Box<[Option<(K, V)>]>,
+    len: usize,
+}
+
+/// A hash map of at most `capacity` entries.
+///
+/// Entries live in a table of [`slots`](#method.slots) slots, the smallest
+/// power of two of at least twice the capacity, with linear probing. An
+/// operation hashes its key once and probes consecutive slots until it finds
+/// the key or a free slot: few on average since the table is at most half
+/// full, and `capacity + 1` in the worst case, when every entry is in one
+/// run. `remove` then also hashes and may move each entry of the run after
+/// the removed one, again at most `capacity`.
+///
+/// ```
+/// use txcell::{atomically, TxHashMap, TxSet};
+///
+/// let ages = TxHashMap::new(2);
+/// atomically(&TxSet::new().write(0), |tx| {
+///     ages.insert(tx, "ada", 36).unwrap();
+///     ages.insert(tx, "alan", 41).unwrap();
+///     assert_eq!(ages.insert(tx, "grace", 85), Err(("grace", 85)));
+///     assert_eq!(ages.insert(tx, "ada", 37), Ok(Some(36)));
+///     assert_eq!(ages.get(tx, &"ada"), Some(37));
+/// });
+/// ```
+#[derive(Debug)]
+pub struct TxHashMap<K, V> {
+    table: TxPtr<Table<K, V>>,
+    capacity: usize,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq + Send, V: Send> TxHashMap<K, V> {
+    /// An empty map with room for `capacity` entries.
+    #[track_caller]
+    pub fn new(capacity: usize) -> TxHashMap<K, V> {
+        TxHashMap {
+            table: TxPtr::new(Self::table(capacity)),
+            capacity,
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// An empty map with room for `capacity` entries, protected by the lock
+    /// of `group`.
+    #[track_caller]
+    pub fn new_in(group: ConflictGroup, capacity: usize) -> TxHashMap<K, V> {
+        TxHashMap {
+            table: TxPtr::new_in(group, Self::table(capacity)),
+            capacity,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn table(capacity: usize) -> Table<K, V> {
+        let slots = (2 * capacity).max(1).next_power_of_two();
+        Table {
+            slots: (0..slots).map(|_| None).collect(),
+            len: 0,
+        }
+    }
+
+    /// The slot `key` hashes to.
+    fn home(&self, key: &K, slots: usize) -> usize {
+        self.hasher.hash_one(key) as usize & (slots - 1)
+    }
+
+    /// The slot holding `key`, or else the free slot that ends its probe
+    /// sequence. The table always has a free slot, as it is at most half full.
+    fn find(&self, table: &Table<K, V>, key: &K) -> Result<usize, usize> {
+        let mask = table.slots.len() - 1;
+        let mut i = self.home(key, table.slots.len());
+        loop {
+            match &table.slots[i] {
+                Some((k, _)) if k == key => return Ok(i),
+                Some(_) => i = (i + 1) & mask,
+                None => return Err(i),
+            }
+        }
+    }
+
+    /// Insert `value` under `key`, returning the value it replaces, or
+    /// return both if the map is full and does not contain `key`.
+    pub fn insert(&self, tx: &TxToken, key: K, value: V) -> Result<Option<V>, (K, V)> {
+        let mut table = self.table.borrow_mut_in(tx);
+        match self.find(&table, &key) {
+            Ok(i) => {
+                let entry = table.slots[i].as_mut().unwrap();
+                Ok(Some(std::mem::replace(&mut entry.1, value)))
+            }
+            Err(_) if table.len == self.capacity => Err((key, value)),
+            Err(i) => {
+                table.slots[i] = Some((key, value));
+                table.len += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    /// A copy of the value under `key`.
+    pub fn get(&self, tx: &TxToken, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let table = self.table.borrow_in(tx);
+        let i = self.find(&table, key).ok()?;
+        table.slots[i].as_ref().map(|(_, v)| v.clone())
+    }
+
+    /// Whether the map has an entry under `key`.
+    pub fn contains_key(&self, tx: &TxToken, key: &K) -> bool {
+        self.find(&self.table.borrow_in(tx), key).is_ok()
+    }
+
+    /// Remove the entry under `key`, returning its value.
+    pub fn remove(&self, tx: &TxToken, key: &K) -> Option<V> {
+        let mut table = self.table.borrow_mut_in(tx);
+        let mut gap = self.find(&table, key).ok()?;
+        let (_, value) = table.slots[gap].take().unwrap();
+        table.len -= 1;
+
+        // Move back the entries after the gap that probed past it, so that
+        // lookups still find them
+        let mask = table.slots.len() - 1;
+        let mut i = gap;
+        loop {
+            i = (i + 1) & mask;
+            let home = match &table.slots[i] {
+                Some((k, _)) => self.home(k, table.slots.len()),
+                None => break,
+            };
+            // Whether `home` lies cyclically in (gap, i]
+            let reachable = if gap < i {
+                gap < home && home <= i
+            } else {
+                gap < home || home <= i
+            };
+            if !reachable {
+                table.slots[gap] = table.slots[i].take();
+                gap = i;
+            }
+        }
+        Some(value)
+    }
+
+    /// How many entries the map holds.
+    pub fn len(&self, tx: &TxToken) -> usize {
+        self.table.borrow_in(tx).len
+    }
+
+    /// Whether the map holds no entries.
+    pub fn is_empty(&self, tx: &TxToken) -> bool {
+        self.table.borrow_in(tx).len == 0
+    }
+
+    /// Whether the map holds `capacity` entries.
+    pub fn is_full(&self, tx: &TxToken) -> bool {
+        self.table.borrow_in(tx).len == self.capacity
+    }
+
+    /// How many entries the map holds at most.
+    ///
+    /// This needs no transaction, as the capacity never changes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of slots in the table.
+    pub fn slots(&self, tx: &TxToken) -> usize {
+        self.table.borrow_in(tx).slots.len()
+    }
+}
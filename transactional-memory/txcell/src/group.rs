@@ -0,0 +1,209 @@
+//! Conflict groups declared by hand.
+//!
+//! The compiler assigns each transaction the locks of the conflict sets it
+//! may touch, from an analysis of where the `TxPtr`s it uses are allocated.
+//! That analysis can be too coarse or too fine. A `TxPtr` or `TxCell` created
+//! with `new_in` names the conflict set that protects it, and its checked
+//! accessors then panic if the current transaction does not hold that lock.
+//! With the `verify` feature, so do the unchecked `borrow`, `borrow_mut`,
+//! `get` and `set` that the compiled `transaction { }` blocks call, which
+//! checks the lock indices the compiler chose against the declared groups.
+//!
+//! A value created with `new` has no declared group. With the `verify`
+//! feature it records where it was created and is bound to the locks the
+//! thread holds the first time it is accessed under any, and a later access
+//! that holds none of them panics with that allocation site.
+
+use crate::{held, LockMode, LockReq};
+use std::fmt;
+#[cfg(feature = "verify")]
+use std::panic::Location;
+#[cfg(feature = "verify")]
+use std::sync::{Mutex, PoisonError};
+
+/// The conflict set, and so the conflict-set lock, that protects a `TxPtr` or
+/// `TxCell`.
+///
+/// Exclusive transactions and read/write transactions lock different tables,
+/// so a group names the table as well as the index: lock 3 of
+/// [`TxSet::mutex`](crate::TxSet::mutex) does not protect a value of
+/// `ConflictGroup::read_write(3)`.
+///
+/// ```
+/// use txcell::{atomically, ConflictGroup, TxPtr, TxSet};
+///
+/// const ACCOUNTS: ConflictGroup = ConflictGroup::read_write(3);
+///
+/// let balance = TxPtr::new_in(ACCOUNTS, 100);
+/// atomically(&TxSet::new().write(ACCOUNTS.index()), |tx| {
+///     *balance.borrow_mut_in(tx) -= 10;
+/// });
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConflictGroup {
+    index: usize,
+    mutex: bool,
+}
+
+impl ConflictGroup {
+    /// The group protected by exclusive conflict-set lock `index`, taken with
+    /// `TxSet::mutex` or `lock_mutex`.
+    pub const fn mutex(index: usize) -> ConflictGroup {
+        ConflictGroup { index, mutex: true }
+    }
+
+    /// The group protected by read/write conflict-set lock `index`, taken
+    /// with `TxSet::read`/`write` or `read_lock_mutex`/`write_lock_mutex`.
+    pub const fn read_write(index: usize) -> ConflictGroup {
+        ConflictGroup {
+            index,
+            mutex: false,
+        }
+    }
+
+    /// The index of the conflict-set lock that protects the group.
+    pub const fn index(self) -> usize {
+        self.index
+    }
+
+    /// Whether the group is protected by an exclusive lock rather than a
+    /// read/write one.
+    pub const fn is_mutex(self) -> bool {
+        self.mutex
+    }
+
+    /// The group whose lock `req` takes.
+    #[cfg(feature = "verify")]
+    fn of(req: &LockReq) -> ConflictGroup {
+        match req.mode {
+            LockMode::Mutex => ConflictGroup::mutex(req.index),
+            LockMode::Read | LockMode::Write => ConflictGroup::read_write(req.index),
+        }
+    }
+
+    /// Whether holding `req` allows the access: the group's own lock, and for
+    /// a write on a read/write group its write lock.
+    fn covered_by(self, req: &LockReq, write: bool) -> bool {
+        let mutex = req.mode == LockMode::Mutex;
+        req.index == self.index
+            && mutex == self.mutex
+            && (mutex || !write || req.mode == LockMode::Write)
+    }
+}
+
+impl fmt::Display for ConflictGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let table = if self.mutex { "mutex" } else { "lock" };
+        write!(f, "{} {}", table, self.index)
+    }
+}
+
+/// Where a `TxPtr` or `TxCell` was created, and with the `verify` feature the
+/// groups whose locks were held at every access since the first one that
+/// held any.
+#[derive(Debug)]
+pub(crate) struct Site {
+    #[cfg(feature = "verify")]
+    location: &'static Location<'static>,
+    #[cfg(feature = "verify")]
+    bound: Mutex<Option<Vec<ConflictGroup>>>,
+}
+
+impl Site {
+    /// The site of the caller.
+    #[track_caller]
+    pub(crate) fn caller() -> Site {
+        Site {
+            #[cfg(feature = "verify")]
+            location: Location::caller(),
+            #[cfg(feature = "verify")]
+            bound: Mutex::new(None),
+        }
+    }
+
+    /// Panic if the value was bound to locks of which the current thread
+    /// holds none for the access, and otherwise narrow the binding to the
+    /// held ones.
+    #[cfg(feature = "verify")]
+    fn check(&self, what: &str, addr: usize, write: bool) {
+        let locks = held::locks();
+        let mut bound = self.bound.lock().unwrap_or_else(PoisonError::into_inner);
+        let groups = match &mut *bound {
+            Some(groups) => groups,
+            None => {
+                // Accesses before the first transaction initialize the value
+                if !locks.is_empty() {
+                    *bound = Some(locks.iter().map(ConflictGroup::of).collect());
+                }
+                return;
+            }
+        };
+        let before = groups.clone();
+        groups.retain(|group| locks.iter().any(|req| group.covered_by(req, write)));
+        if groups.is_empty() {
+            *groups = before;
+            let names: Vec<String> = groups.iter().map(ToString::to_string).collect();
+            let read_write = groups.iter().any(|group| !group.is_mutex());
+            let writing = if write && read_write {
+                " for writing"
+            } else {
+                ""
+            };
+            drop(bound);
+            panic!(
+                "txcell: {} at {:#x} allocated at {} is {} without holding {}{}, which it \
+                 was first accessed under; held locks: {:?}",
+                what,
+                addr,
+                self.location,
+                if write { "written" } else { "read" },
+                names.join(" or "),
+                writing,
+                locks
+            );
+        }
+    }
+}
+
+/// Panic unless the current thread holds the lock of `group` for the access:
+/// any lock of its conflict set for a read, the exclusive or the write lock
+/// for a write. `what` and `addr` name the accessed value.
+///
+/// With the `verify` feature, a value without a group is checked against the
+/// locks of `site` instead.
+#[cfg_attr(not(feature = "verify"), allow(unused_variables))]
+pub(crate) fn check(
+    what: &str,
+    addr: usize,
+    group: Option<ConflictGroup>,
+    site: &Site,
+    write: bool,
+) {
+    let group = match group {
+        Some(group) => group,
+        None => {
+            #[cfg(feature = "verify")]
+            site.check(what, addr, write);
+            return;
+        }
+    };
+    let locks = held::locks();
+    if !locks.iter().any(|req| group.covered_by(req, write)) {
+        let writing = if write && !group.is_mutex() {
+            " for writing"
+        } else {
+            ""
+        };
+        panic!(
+            "txcell: {} at {:#x} in conflict group {} is {} without holding {}{}; \
+             held locks: {:?}",
+            what,
+            addr,
+            group.index(),
+            if write { "written" } else { "read" },
+            group,
+            writing,
+            locks
+        );
+    }
+}
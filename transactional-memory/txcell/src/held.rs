@@ -0,0 +1,142 @@
+//! The conflict-set locks held by the current thread, which make transactions
+//! reentrant.
+//!
+//! A transaction in a function called from another transaction takes its
+//! locks again. A lock the thread already holds is not acquired a second
+//! time, which would deadlock on a ticket lock; its depth is counted up
+//! instead, and the lock is released when the outermost transaction releases
+//! it.
+//!
+//! A nested write on a phase-fair lock held for reading panics. Upgrading
+//! would mean releasing the read lock and taking the write lock while the
+//! thread holds locks of higher index, out of the lock order, and would let
+//! other writers change what the outer transaction has read. An outer
+//! transaction that may write in a nested one has to take the write lock
+//! itself. A nested read of a lock held for writing is just nested.
+
+use crate::{acquire, release, LockMode, LockReq};
+use std::cell::{Cell, RefCell};
+
+/// A conflict-set lock the current thread holds.
+struct Held {
+    index: usize,
+    mode: LockMode,
+    /// Transactions of this thread in the lock
+    depth: usize,
+}
+
+thread_local! {
+    static HELD: RefCell<Vec<Held>> = const { RefCell::new(Vec::new()) };
+    /// Serial number of the thread's latest outermost transaction
+    static TRANSACTION: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The transaction the current thread is in, as a serial number that is new
+/// for every outermost transaction, or `None` outside of transactions.
+pub(crate) fn transaction() -> Option<u64> {
+    let in_transaction = HELD.with(|held| !held.borrow().is_empty());
+    if in_transaction {
+        Some(TRANSACTION.with(Cell::get))
+    } else {
+        None
+    }
+}
+
+/// The locks the current thread holds, sorted by index.
+pub(crate) fn locks() -> Vec<LockReq> {
+    let mut locks: Vec<LockReq> = HELD.with(|held| {
+        held.borrow()
+            .iter()
+            .map(|h| LockReq {
+                index: h.index,
+                mode: h.mode,
+            })
+            .collect()
+    });
+    locks.sort_by_key(|req| (req.index, req.mode == LockMode::Mutex));
+    locks
+}
+
+/// Whether modes `a` and `b` lock the same table: the ticket locks or the
+/// phase-fair locks.
+fn same_table(a: LockMode, b: LockMode) -> bool {
+    (a == LockMode::Mutex) == (b == LockMode::Mutex)
+}
+
+/// Take lock `index` in `mode` for a transaction of the current thread,
+/// unless the thread holds it already.
+///
+/// # Panics
+///
+/// If the thread holds the lock for reading and `mode` is a write.
+pub(crate) fn enter(index: usize, mode: LockMode) {
+    let new = HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        match held
+            .iter_mut()
+            .find(|h| h.index == index && same_table(h.mode, mode))
+        {
+            Some(h) => {
+                if h.mode == LockMode::Read && mode == LockMode::Write {
+                    panic!(
+                        "txcell: nested transaction writes conflict-set lock {}, which an outer \
+                         transaction of this thread holds for reading",
+                        index
+                    );
+                }
+                h.depth += 1;
+                false
+            }
+            None => {
+                if held.is_empty() {
+                    TRANSACTION.with(|t| t.set(t.get() + 1));
+                }
+                held.push(Held {
+                    index,
+                    mode,
+                    depth: 1,
+                });
+                true
+            }
+        }
+    });
+
+    if new {
+        acquire(index, mode);
+    }
+}
+
+/// Leave lock `index` after a transaction of the current thread, releasing
+/// it if this was the outermost one.
+///
+/// # Panics
+///
+/// If the thread does not hold the lock.
+pub(crate) fn exit(index: usize, mode: LockMode) {
+    let released = HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        let i = match held
+            .iter()
+            .position(|h| h.index == index && same_table(h.mode, mode))
+        {
+            Some(i) => i,
+            None => panic!(
+                "txcell: transaction releases conflict-set lock {} ({:?}), which this thread \
+                 does not hold",
+                index, mode
+            ),
+        };
+        held[i].depth -= 1;
+        if held[i].depth == 0 {
+            Some(held.swap_remove(i).mode)
+        } else {
+            None
+        }
+    });
+
+    // Release in the mode the lock is held in, which a nested read does not
+    // change
+    if let Some(mode) = released {
+        release(index, mode);
+    }
+}
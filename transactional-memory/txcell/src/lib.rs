@@ -1,166 +1,432 @@
-#![feature(lang_items)]
-use pflock::PFLock;
+#![cfg_attr(feature = "compiler", feature(lang_items))]
+use pflock::RawPFLock;
 use std::cell::{Cell, UnsafeCell};
-use std::sync::atomic::{AtomicUsize, Ordering};
 
+pub mod collections;
+mod group;
+mod held;
+mod protocol;
+mod registry;
+mod token;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod tree;
+mod tx;
 
-/// TODO: Docs here
-///
-/// [`new`]: #method.new
-/// [`set`]: #method.set
-/// [`get`]: #method.get
-///
+pub use collections::{TxHashMap, TxQueue, TxVec};
+pub use group::ConflictGroup;
+use group::Site;
+pub use protocol::{TicketLock, TxLockProtocol};
+use registry::{make_locks, LockTable};
+pub use token::{TxRef, TxRefMut, TxToken};
+pub use tx::{atomically, TxSet};
 
-struct TicketLock {
-    now_serving: AtomicUsize,
-    next_ticket: AtomicUsize,
-}
+/// Number of conflict-set locks allocated if the program does not call
+/// [`declare_locks`] before its first transaction.
+pub const DEFAULT_LOCKS: usize = 20;
 
-impl TicketLock {
-    const fn new() -> Self {
-        TicketLock {
-            now_serving: AtomicUsize::new(0),
-            next_ticket: AtomicUsize::new(0),
-        }
-    }
+// Locks of exclusive transactions, and of those that separate reads and writes
+static MUTEXES: LockTable<TicketLock> = LockTable::new();
+static PFLOCKS: LockTable<RawPFLock> = LockTable::new();
 
-    fn lock(&self) {
-        let my_ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
-        while self.now_serving.load(Ordering::Relaxed) != my_ticket {}
+/// Allocate one lock for each of the `count` conflict sets of the program.
+///
+/// Call this at startup, before the first transaction, with the number of
+/// conflict sets the compiler assigned. Without it, the first transaction
+/// allocates [`DEFAULT_LOCKS`] locks, and a transaction on a conflict set
+/// beyond them panics.
+///
+/// # Panics
+///
+/// If the locks were already allocated with a different count.
+pub fn declare_locks(count: usize) {
+    let mutexes = MUTEXES.declare(make_locks::<TicketLock>(count));
+    let pflocks = PFLOCKS.declare(make_locks::<RawPFLock>(count));
+    for allocated in [mutexes, pflocks].iter().filter_map(|r| r.err()) {
+        assert!(
+            allocated == count,
+            "txcell: cannot declare {} conflict-set locks, {} are already allocated",
+            count,
+            allocated
+        );
     }
+}
 
-    fn unlock(&self) {
-        self.now_serving.store(
-            self.now_serving.load(Ordering::Relaxed) + 1,
-            Ordering::Relaxed,
+/// Like [`declare_locks`], but run every transaction on locks of protocol
+/// `P` instead of the default [`TicketLock`]s and [`RawPFLock`]s.
+///
+/// This registers the protocol for the whole program, so it has to be called
+/// before the first transaction.
+///
+/// # Panics
+///
+/// If the locks were already allocated.
+pub fn declare_locks_with<P: TxLockProtocol + 'static>(count: usize) {
+    let mutexes = MUTEXES.declare(make_locks::<P>(count));
+    let pflocks = PFLOCKS.declare(make_locks::<P>(count));
+    // Even with the same count, existing tables hold locks of another protocol
+    if let Err(allocated) = mutexes.and(pflocks) {
+        panic!(
+            "txcell: cannot declare {} conflict-set locks of a protocol, {} are already allocated",
+            count, allocated
         );
     }
 }
 
-static mut MUTEXES: [TicketLock; 20] = [
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-    TicketLock::new(),
-];
-
-static mut PFLOCKS: [PFLock; 20] = [
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-    PFLock::new(),
-];
-
-pub struct TxCell<T>(Cell<T>);
+pub struct TxCell<T> {
+    value: Cell<T>,
+    group: Option<ConflictGroup>,
+    site: Site,
+}
 
 unsafe impl<T> Send for TxCell<T> {}
 unsafe impl<T> Sync for TxCell<T> {}
 
 impl<T: Copy> TxCell<T> {
+    #[track_caller]
     pub fn new(inner: T) -> TxCell<T> {
-        TxCell(Cell::new(inner))
+        TxCell {
+            value: Cell::new(inner),
+            group: None,
+            site: Site::caller(),
+        }
     }
 
+    /// Create a new `TxCell` containing `inner`, protected by the lock of
+    /// `group`.
+    #[track_caller]
+    pub fn new_in(group: ConflictGroup, inner: T) -> TxCell<T> {
+        TxCell {
+            value: Cell::new(inner),
+            group: Some(group),
+            site: Site::caller(),
+        }
+    }
+
+    /// The conflict group given to [`new_in`](#method.new_in).
+    pub fn group(&self) -> Option<ConflictGroup> {
+        self.group
+    }
+
+    /// With the `verify` feature, panics if the `TxCell` has a conflict group
+    /// whose lock the current thread does not hold for writing, or has none
+    /// and the thread holds none of the locks it was first accessed under.
     pub fn set(&self, val: T) {
-        self.0.set(val)
+        #[cfg(feature = "verify")]
+        group::check(
+            "TxCell",
+            self.value.as_ptr() as usize,
+            self.group,
+            &self.site,
+            true,
+        );
+        self.value.set(val)
     }
 
+    /// With the `verify` feature, panics if the `TxCell` has a conflict group
+    /// whose lock the current thread does not hold, or has none and the
+    /// thread holds none of the locks it was first accessed under.
     pub fn get(&self) -> T {
-        self.0.get()
+        #[cfg(feature = "verify")]
+        group::check(
+            "TxCell",
+            self.value.as_ptr() as usize,
+            self.group,
+            &self.site,
+            false,
+        );
+        self.value.get()
+    }
+
+    /// Set the value in the transaction of `tx`.
+    ///
+    /// # Panics
+    ///
+    /// If the transaction of `tx` has ended, or the `TxCell` has a conflict
+    /// group whose lock the transaction does not hold for writing.
+    pub fn set_in(&self, tx: &TxToken, val: T) {
+        let addr = self.value.as_ptr() as usize;
+        tx.check(addr);
+        group::check("TxCell", addr, self.group, &self.site, true);
+        self.value.set(val)
+    }
+
+    /// Get the value in the transaction of `tx`.
+    ///
+    /// # Panics
+    ///
+    /// If the transaction of `tx` has ended, or the `TxCell` has a conflict
+    /// group whose lock the transaction does not hold.
+    pub fn get_in(&self, tx: &TxToken) -> T {
+        let addr = self.value.as_ptr() as usize;
+        tx.check(addr);
+        group::check("TxCell", addr, self.group, &self.site, false);
+        self.value.get()
     }
 }
 
 #[derive(Debug)]
-pub struct TxPtr<T>(UnsafeCell<T>);
+pub struct TxPtr<T> {
+    value: UnsafeCell<T>,
+    group: Option<ConflictGroup>,
+    site: Site,
+}
 
 unsafe impl<T> Send for TxPtr<T> {}
 unsafe impl<T> Sync for TxPtr<T> {}
 
 impl<T> TxPtr<T> {
     /// Create a new `TxPtr` containing `inner`.
+    #[track_caller]
     pub fn new(inner: T) -> TxPtr<T> {
-        TxPtr(UnsafeCell::new(inner))
+        TxPtr {
+            value: UnsafeCell::new(inner),
+            group: None,
+            site: Site::caller(),
+        }
+    }
+
+    /// Create a new `TxPtr` containing `inner`, protected by the lock of
+    /// `group`.
+    ///
+    /// The checked borrows then also panic if the transaction does not hold
+    /// that lock, which catches conflict sets the compiler merged or split
+    /// wrongly.
+    #[track_caller]
+    pub fn new_in(group: ConflictGroup, inner: T) -> TxPtr<T> {
+        TxPtr {
+            value: UnsafeCell::new(inner),
+            group: Some(group),
+            site: Site::caller(),
+        }
+    }
+
+    /// The conflict group given to [`new_in`](#method.new_in).
+    pub fn group(&self) -> Option<ConflictGroup> {
+        self.group
     }
 
     /// Immutably borrows the wrapped value.
+    ///
+    /// Nothing is checked: this must only be called inside a transaction that
+    /// holds the lock of the `TxPtr`'s conflict set, or while no other thread
+    /// can access it. [`borrow_in`](#method.borrow_in) checks the borrow.
+    ///
+    /// With the `verify` feature, panics if the `TxPtr` has a conflict group
+    /// whose lock the current thread does not hold, or has none and the
+    /// thread holds none of the locks it was first accessed under.
     pub fn borrow(&self) -> &T {
-        unsafe { &*self.0.get() }
+        #[cfg(feature = "verify")]
+        group::check(
+            "TxPtr",
+            self.value.get() as usize,
+            self.group,
+            &self.site,
+            false,
+        );
+        unsafe { &*self.value.get() }
     }
 
     /// Mutably borrows the wrapped value.
     ///
-    /// The borrow lasts until the returned `RefMut` or all `RefMuts` derived from it
-    /// exit scope. The value cannot be borrowed while this borrow is active.
+    /// Nothing is checked: this must only be called inside a transaction that
+    /// holds the lock of the `TxPtr`'s conflict set for writing, and the
+    /// returned reference must not overlap with any other borrow of the same
+    /// `TxPtr`. [`borrow_mut_in`](#method.borrow_mut_in) checks the borrow.
+    ///
+    /// With the `verify` feature, panics if the `TxPtr` has a conflict group
+    /// whose lock the current thread does not hold for writing, or has none
+    /// and the thread holds none of the locks it was first accessed under.
+    pub fn borrow_mut(&self) -> &mut T {
+        #[cfg(feature = "verify")]
+        group::check(
+            "TxPtr",
+            self.value.get() as usize,
+            self.group,
+            &self.site,
+            true,
+        );
+        unsafe { &mut *self.value.get() }
+    }
+
+    /// Immutably borrows the wrapped value in the transaction of `tx`.
     ///
     /// # Panics
     ///
-    /// Panics if the value is currently borrowed.
-    pub fn borrow_mut(&self) -> &mut T {
-        unsafe { &mut *self.0.get() }
+    /// If the transaction of `tx` has ended, the value is currently mutably
+    /// borrowed in it, or the `TxPtr` has a conflict group whose lock the
+    /// transaction does not hold.
+    pub fn borrow_in<'a>(&'a self, tx: &'a TxToken) -> TxRef<'a, T> {
+        let addr = self.value.get() as usize;
+        tx.check(addr);
+        group::check("TxPtr", addr, self.group, &self.site, false);
+        token::borrow(tx, addr, false);
+        TxRef::new(unsafe { &*self.value.get() }, tx)
+    }
+
+    /// Mutably borrows the wrapped value in the transaction of `tx`.
+    ///
+    /// The borrow lasts until the returned `TxRefMut` is dropped.
+    ///
+    /// # Panics
+    ///
+    /// If the transaction of `tx` has ended, the value is currently borrowed
+    /// in it, or the `TxPtr` has a conflict group whose lock the transaction
+    /// does not hold for writing.
+    pub fn borrow_mut_in<'a>(&'a self, tx: &'a TxToken) -> TxRefMut<'a, T> {
+        let addr = self.value.get() as usize;
+        tx.check(addr);
+        group::check("TxPtr", addr, self.group, &self.site, true);
+        token::borrow(tx, addr, true);
+        TxRefMut::new(unsafe { &mut *self.value.get() }, tx)
     }
 }
 
-/// Simple spinlock. Spin until we set the `AtomicBool` from `false` to `true`.
-#[lang = "transaction_lock"]
+/// Block until lock `index` is held in `mode`.
+fn acquire(index: usize, mode: LockMode) {
+    #[cfg(feature = "trace")]
+    let start = trace::Start::now();
+    match mode {
+        LockMode::Mutex => MUTEXES.get(index).lock(),
+        LockMode::Read => PFLOCKS.get(index).read_lock(),
+        LockMode::Write => PFLOCKS.get(index).lock(),
+    }
+    #[cfg(feature = "trace")]
+    trace::acquired(index, mode, start);
+}
+
+/// Release lock `index`, held in `mode` by this thread.
+fn release(index: usize, mode: LockMode) {
+    #[cfg(feature = "trace")]
+    let end = std::time::Instant::now();
+    // `held` only releases locks the thread holds
+    unsafe {
+        match mode {
+            LockMode::Mutex => MUTEXES.get(index).unlock(),
+            LockMode::Read => PFLOCKS.get(index).read_unlock(),
+            LockMode::Write => PFLOCKS.get(index).unlock(),
+        }
+    }
+    #[cfg(feature = "trace")]
+    trace::released(index, mode, end);
+}
+
+/// Take the exclusive lock of conflict set `n`.
+///
+/// Transactions are reentrant: a thread that holds the lock already, from an
+/// enclosing transaction, keeps it until that transaction ends.
+#[cfg_attr(feature = "compiler", lang = "transaction_lock")]
 pub fn lock_mutex(n: usize) {
-    unsafe { MUTEXES[n].lock() }
+    held::enter(n, LockMode::Mutex)
 }
 
-/// Simple spinlock. Set the `AtomicBool` to `false`.
-#[lang = "transaction_unlock"]
+/// Release the exclusive lock of conflict set `n`.
+#[cfg_attr(feature = "compiler", lang = "transaction_unlock")]
 pub fn unlock_mutex(n: usize) {
-    unsafe { MUTEXES[n].unlock() }
+    held::exit(n, LockMode::Mutex)
 }
 
-#[lang = "transaction_write_lock"]
+#[cfg_attr(feature = "compiler", lang = "transaction_write_lock")]
 pub fn write_lock_mutex(n: usize) {
-    unsafe { PFLOCKS[n].write_lock() }
+    held::enter(n, LockMode::Write)
 }
 
-#[lang = "transaction_write_unlock"]
+#[cfg_attr(feature = "compiler", lang = "transaction_write_unlock")]
 pub fn write_unlock_mutex(n: usize) {
-    unsafe { PFLOCKS[n].write_unlock() }
+    held::exit(n, LockMode::Write)
 }
 
-#[lang = "transaction_read_lock"]
+#[cfg_attr(feature = "compiler", lang = "transaction_read_lock")]
 pub fn read_lock_mutex(n: usize) {
-    unsafe { PFLOCKS[n].read_lock() }
+    held::enter(n, LockMode::Read)
 }
 
-#[lang = "transaction_read_unlock"]
+#[cfg_attr(feature = "compiler", lang = "transaction_read_unlock")]
 pub fn read_unlock_mutex(n: usize) {
-    unsafe { PFLOCKS[n].read_unlock() }
+    held::exit(n, LockMode::Read)
+}
+
+/// Which lock of a conflict set a transaction takes, and how.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// The ticket lock of `transaction_lock`
+    Mutex,
+    /// The phase-fair lock, for reading
+    Read,
+    /// The phase-fair lock, for writing
+    Write,
+}
+
+/// One conflict-set lock of a transaction that takes several at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockReq {
+    pub index: usize,
+    pub mode: LockMode,
+}
+
+impl LockReq {
+    pub const fn mutex(index: usize) -> Self {
+        LockReq {
+            index,
+            mode: LockMode::Mutex,
+        }
+    }
+
+    pub const fn read(index: usize) -> Self {
+        LockReq {
+            index,
+            mode: LockMode::Read,
+        }
+    }
+
+    pub const fn write(index: usize) -> Self {
+        LockReq {
+            index,
+            mode: LockMode::Write,
+        }
+    }
+}
+
+/// Whether `reqs` is in canonical order: sorted by lock index, with each
+/// index at most once.
+fn is_canonical(reqs: &[LockReq]) -> bool {
+    reqs.windows(2).all(|pair| pair[0].index < pair[1].index)
+}
+
+/// Take the locks of every conflict set in `reqs`, in order.
+///
+/// `reqs` must be sorted by lock index without duplicates. Since every
+/// transaction then takes its locks in the same order, two transactions
+/// touching the same conflict sets cannot deadlock. Debug builds check the
+/// order and panic on an unsorted or duplicate request.
+pub fn transaction_lock_set(reqs: &[LockReq]) {
+    debug_assert!(
+        is_canonical(reqs),
+        "txcell: lock set must be sorted by index without duplicates: {:?}",
+        reqs
+    );
+    for req in reqs {
+        match req.mode {
+            LockMode::Mutex => lock_mutex(req.index),
+            LockMode::Read => read_lock_mutex(req.index),
+            LockMode::Write => write_lock_mutex(req.index),
+        }
+    }
+}
+
+/// Release the locks taken by `transaction_lock_set` with the same `reqs`,
+/// in reverse order.
+pub fn transaction_unlock_set(reqs: &[LockReq]) {
+    debug_assert!(
+        is_canonical(reqs),
+        "txcell: lock set must be sorted by index without duplicates: {:?}",
+        reqs
+    );
+    for req in reqs.iter().rev() {
+        match req.mode {
+            LockMode::Mutex => unlock_mutex(req.index),
+            LockMode::Read => read_unlock_mutex(req.index),
+            LockMode::Write => write_unlock_mutex(req.index),
+        }
+    }
 }
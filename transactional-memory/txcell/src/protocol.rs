@@ -0,0 +1,103 @@
+//! The locking protocols the transaction runtime can run on.
+
+use pflock::RawPFLock;
+use std::hint::spin_loop;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A lock protocol for the conflict-set locks of transactions.
+///
+/// The runtime keeps one lock per conflict set and calls `lock`/`unlock` for
+/// exclusive transactions and `read_lock`/`read_unlock` for read-only ones.
+/// A protocol without shared access, like [`TicketLock`], treats reads as
+/// exclusive. Implement this for MCS locks, priority-ordered locks or ceiling
+/// protocols and select one with
+/// [`declare_locks_with`](crate::declare_locks_with).
+pub trait TxLockProtocol: Sync {
+    /// A new, unlocked lock.
+    fn new() -> Self
+    where
+        Self: Sized;
+
+    /// Block until the lock is held exclusively.
+    fn lock(&self);
+
+    /// Release an exclusive lock.
+    ///
+    /// # Safety
+    ///
+    /// The calling thread must hold the lock exclusively.
+    unsafe fn unlock(&self);
+
+    /// Block until the lock is held for reading.
+    fn read_lock(&self);
+
+    /// Release a read lock.
+    ///
+    /// # Safety
+    ///
+    /// The calling thread must hold the lock for reading.
+    unsafe fn read_unlock(&self);
+}
+
+/// A FIFO spinlock: each thread takes a ticket and waits until it is served.
+pub struct TicketLock {
+    now_serving: AtomicUsize,
+    next_ticket: AtomicUsize,
+}
+
+impl TxLockProtocol for TicketLock {
+    fn new() -> Self {
+        TicketLock {
+            now_serving: AtomicUsize::new(0),
+            next_ticket: AtomicUsize::new(0),
+        }
+    }
+
+    fn lock(&self) {
+        let my_ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        // Acquire the critical section of the holder that served us
+        while self.now_serving.load(Ordering::Acquire) != my_ticket {
+            spin_loop();
+        }
+    }
+
+    unsafe fn unlock(&self) {
+        // Only the holder writes now_serving, and publishes its critical
+        // section to the next ticket with it
+        self.now_serving.store(
+            self.now_serving.load(Ordering::Relaxed) + 1,
+            Ordering::Release,
+        );
+    }
+
+    fn read_lock(&self) {
+        self.lock()
+    }
+
+    unsafe fn read_unlock(&self) {
+        self.unlock()
+    }
+}
+
+/// The phase-fair reader-writer lock: readers wait for at most one writer.
+impl TxLockProtocol for RawPFLock {
+    fn new() -> Self {
+        RawPFLock::new()
+    }
+
+    fn lock(&self) {
+        self.write_lock()
+    }
+
+    unsafe fn unlock(&self) {
+        self.write_unlock()
+    }
+
+    fn read_lock(&self) {
+        RawPFLock::read_lock(self)
+    }
+
+    unsafe fn read_unlock(&self) {
+        RawPFLock::read_unlock(self)
+    }
+}
@@ -0,0 +1,84 @@
+//! Tables of the conflict-set locks taken by the transaction lang items.
+//!
+//! The compiler numbers the conflict sets of a program and passes the number
+//! of a transaction's set to `transaction_lock(n)` and friends. Each table
+//! holds one lock per conflict set and is allocated once, either by
+//! [`declare_locks`](crate::declare_locks) at startup or with
+//! [`DEFAULT_LOCKS`](crate::DEFAULT_LOCKS) locks of the default protocol `D`
+//! by the first transaction.
+
+use crate::TxLockProtocol;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub(crate) type Locks = Vec<Box<dyn TxLockProtocol>>;
+
+/// One lock per conflict set, allocated on first use and never freed.
+pub(crate) struct LockTable<D> {
+    locks: AtomicPtr<Locks>,
+    default: PhantomData<fn() -> D>,
+}
+
+impl<D> LockTable<D> {
+    pub(crate) const fn new() -> Self {
+        LockTable {
+            locks: AtomicPtr::new(ptr::null_mut()),
+            default: PhantomData,
+        }
+    }
+}
+
+/// `count` locks of protocol `P`.
+pub(crate) fn make_locks<P: TxLockProtocol + 'static>(count: usize) -> Locks {
+    (0..count)
+        .map(|_| Box::new(P::new()) as Box<dyn TxLockProtocol>)
+        .collect()
+}
+
+impl<D: TxLockProtocol + 'static> LockTable<D> {
+    /// Install `locks` as the table, unless it already exists. Returns the
+    /// number of locks in the existing table if `locks` were not installed.
+    pub(crate) fn declare(&self, locks: Locks) -> Result<(), usize> {
+        let new = Box::into_raw(Box::new(locks));
+        match self.locks.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Ok(()),
+            Err(existing) => {
+                // Another thread got there first; its table is the one in use
+                drop(unsafe { Box::from_raw(new) });
+                Err(unsafe { (*existing).len() })
+            }
+        }
+    }
+
+    /// The lock of conflict set `n`.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not below the number of declared locks.
+    pub(crate) fn get(&self, n: usize) -> &dyn TxLockProtocol {
+        let mut locks = self.locks.load(Ordering::Acquire);
+        if locks.is_null() {
+            // Whichever thread installs its table first, there is one now
+            let _ = self.declare(make_locks::<D>(crate::DEFAULT_LOCKS));
+            locks = self.locks.load(Ordering::Acquire);
+        }
+        // The table is leaked once published, so the reference lives forever
+        let locks = unsafe { &*locks };
+        match locks.get(n) {
+            Some(lock) => &**lock,
+            None => panic!(
+                "txcell: transaction lock {} is out of range, only {} conflict-set locks \
+                 are declared; call txcell::declare_locks with the number of conflict sets \
+                 before the first transaction",
+                n,
+                locks.len()
+            ),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Checked access to `TxPtr`s from inside a transaction.
+//!
+//! `TxPtr::borrow` and `borrow_mut` trust the compiler to call them only
+//! inside a transaction that holds the right lock, and hand out references
+//! without any checks. The `_in` variants take a [`TxToken`] for the current
+//! transaction instead and track the borrows of each thread like a `RefCell`:
+//! borrowing with the token of a transaction that has ended, or borrowing a
+//! `TxPtr` that is mutably borrowed already, panics.
+
+use crate::{held, LockReq};
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// Proof that the current thread is in a transaction.
+///
+/// [`atomically`](crate::atomically) passes one to its closure. Inside a
+/// `transaction { }` block, get one with [`TxToken::with_current`]. Tokens
+/// are only lent to a closure, so neither they nor the borrows made with
+/// them outlive the transaction:
+///
+/// ```compile_fail
+/// use txcell::{atomically, TxPtr, TxSet};
+///
+/// let a = TxPtr::new(0);
+/// let escaped = atomically(&TxSet::new().write(0), |tx| a.borrow_mut_in(tx));
+/// ```
+pub struct TxToken {
+    transaction: u64,
+    // A transaction belongs to one thread
+    _not_send: PhantomData<*const ()>,
+}
+
+impl TxToken {
+    /// Run `f` with a token for the transaction the current thread is in.
+    ///
+    /// # Panics
+    ///
+    /// If the thread is not in a transaction.
+    pub fn with_current<R, F: FnOnce(&TxToken) -> R>(f: F) -> R {
+        match held::transaction() {
+            Some(transaction) => f(&TxToken {
+                transaction,
+                _not_send: PhantomData,
+            }),
+            None => panic!(
+                "txcell: TxToken::with_current called outside of a transaction; wrap the code \
+                 in a `transaction` block or in `txcell::atomically`"
+            ),
+        }
+    }
+
+    /// The conflict-set locks the current thread holds, sorted by index.
+    pub fn held_locks(&self) -> Vec<LockReq> {
+        held::locks()
+    }
+
+    /// Panic unless the token's transaction is still running, which it is
+    /// unless its locks were released by hand inside the closure.
+    pub(crate) fn check(&self, addr: usize) {
+        if held::transaction() != Some(self.transaction) {
+            panic!(
+                "txcell: TxPtr at {:#x} borrowed with the token of a transaction that has \
+                 ended; borrow only inside the transaction the token came from",
+                addr
+            );
+        }
+    }
+}
+
+/// A borrow of a `TxPtr` by the current thread.
+struct Borrow {
+    addr: usize,
+    transaction: u64,
+    /// Shared borrows, or -1 for a mutable one
+    state: isize,
+}
+
+thread_local! {
+    static BORROWS: RefCell<Vec<Borrow>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Record a borrow of the `TxPtr` at `addr` in `tx`'s transaction.
+pub(crate) fn borrow(tx: &TxToken, addr: usize, mutable: bool) {
+    tx.check(addr);
+    BORROWS.with(|borrows| {
+        let mut borrows = borrows.borrow_mut();
+        // Borrows leaked by earlier transactions no longer count
+        borrows.retain(|b| b.transaction == tx.transaction);
+        match borrows.iter_mut().find(|b| b.addr == addr) {
+            None => borrows.push(Borrow {
+                addr,
+                transaction: tx.transaction,
+                state: if mutable { -1 } else { 1 },
+            }),
+            Some(b) if b.state < 0 => panic!(
+                "txcell: TxPtr at {:#x} is already mutably borrowed in this transaction",
+                addr
+            ),
+            Some(_) if mutable => panic!(
+                "txcell: TxPtr at {:#x} is already borrowed in this transaction, it cannot \
+                 be borrowed mutably too",
+                addr
+            ),
+            Some(b) => b.state += 1,
+        }
+    });
+}
+
+/// Record the end of a borrow of the `TxPtr` at `addr`.
+fn release(addr: usize, transaction: u64) {
+    // The thread-local is gone if the guard is dropped during thread exit
+    let _ = BORROWS.try_with(|borrows| {
+        let mut borrows = borrows.borrow_mut();
+        if let Some(i) = borrows
+            .iter()
+            .position(|b| b.addr == addr && b.transaction == transaction)
+        {
+            if borrows[i].state > 1 {
+                borrows[i].state -= 1;
+            } else {
+                borrows.swap_remove(i);
+            }
+        }
+    });
+}
+
+/// A checked shared borrow of a `TxPtr`, from [`TxPtr::borrow_in`](crate::TxPtr::borrow_in).
+pub struct TxRef<'a, T> {
+    value: &'a T,
+    transaction: u64,
+}
+
+impl<'a, T> TxRef<'a, T> {
+    pub(crate) fn new(value: &'a T, tx: &'a TxToken) -> Self {
+        TxRef {
+            value,
+            transaction: tx.transaction,
+        }
+    }
+}
+
+impl<T> Deref for TxRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> Drop for TxRef<'_, T> {
+    fn drop(&mut self) {
+        release(self.value as *const T as usize, self.transaction)
+    }
+}
+
+/// A checked mutable borrow of a `TxPtr`, from
+/// [`TxPtr::borrow_mut_in`](crate::TxPtr::borrow_mut_in).
+pub struct TxRefMut<'a, T> {
+    value: &'a mut T,
+    transaction: u64,
+}
+
+impl<'a, T> TxRefMut<'a, T> {
+    pub(crate) fn new(value: &'a mut T, tx: &'a TxToken) -> Self {
+        TxRefMut {
+            value,
+            transaction: tx.transaction,
+        }
+    }
+}
+
+impl<T> Deref for TxRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for TxRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T> Drop for TxRefMut<'_, T> {
+    fn drop(&mut self) {
+        release(self.value as *const T as usize, self.transaction)
+    }
+}
@@ -0,0 +1,354 @@
+//! Traces of the conflict-set locks taken by transactions, for blocking
+//! analysis.
+//!
+//! With the `trace` feature, every lock taken through the transaction lang
+//! items is recorded with its conflict-set index, its mode, how long the
+//! thread spun for it and how long it held it. Each thread writes to its own
+//! ring buffer of [`capacity`] events without locking; once a buffer is full,
+//! the oldest events are overwritten. Buffers outlive their threads, so after
+//! a run [`events`] returns the events of all threads, and [`write_csv`] and
+//! [`write_json`] dump them for the analysis scripts. A thread that starts
+//! tracing takes over the buffer of one that exited, if there is one, so
+//! there are never more buffers than threads that were tracing at once.
+
+use crate::LockMode;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Events each thread keeps if [`set_capacity`] is not called.
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+/// Head of the list of all rings, newest first.
+static RINGS: AtomicPtr<Ring> = AtomicPtr::new(ptr::null_mut());
+
+/// One lock acquisition, from the start of spinning to the release.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Sequential id of the thread, in the order threads first took a lock
+    pub thread: u64,
+    /// Conflict-set lock index
+    pub index: usize,
+    pub mode: LockMode,
+    /// When the thread started waiting, in nanoseconds since the Unix epoch
+    pub timestamp_ns: u64,
+    /// How long the thread spun for the lock
+    pub spin_ns: u64,
+    /// How long the thread held the lock
+    pub hold_ns: u64,
+}
+
+/// Set how many events each thread keeps. Only threads that take their
+/// first lock afterwards get the new capacity.
+pub fn set_capacity(events: usize) {
+    assert!(events > 0, "txcell: trace capacity must be at least 1");
+    CAPACITY.store(events, Ordering::Relaxed);
+}
+
+/// How many events each new thread keeps.
+pub fn capacity() -> usize {
+    CAPACITY.load(Ordering::Relaxed)
+}
+
+/// The trace id of the current thread, if it has taken a lock yet.
+pub fn thread_id() -> Option<u64> {
+    LOCAL.with(|local| local.borrow().as_ref().map(|local| local.thread))
+}
+
+/// The recorded events of all threads, ordered by timestamp.
+///
+/// Meant to be called after a run. Events written while this reads them are
+/// skipped rather than torn.
+pub fn events() -> Vec<TraceEvent> {
+    let mut events = Vec::new();
+    let mut ring = RINGS.load(Ordering::Acquire);
+    while !ring.is_null() {
+        // Rings are leaked once published
+        let r = unsafe { &*ring };
+        r.collect(&mut events);
+        ring = r.next;
+    }
+    events.sort_by_key(|e| (e.timestamp_ns, e.thread));
+    events
+}
+
+/// Forget the events recorded so far, e.g. after a warm-up phase.
+pub fn clear() {
+    let mut ring = RINGS.load(Ordering::Acquire);
+    while !ring.is_null() {
+        let r = unsafe { &*ring };
+        r.cleared.store(r.written.load(Ordering::Acquire), Ordering::Release);
+        ring = r.next;
+    }
+}
+
+/// Write the events as CSV, with a header row.
+pub fn write_csv<W: Write>(mut out: W) -> io::Result<()> {
+    writeln!(out, "thread,index,mode,timestamp_ns,spin_ns,hold_ns")?;
+    for e in events() {
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            e.thread,
+            e.index,
+            mode_name(e.mode),
+            e.timestamp_ns,
+            e.spin_ns,
+            e.hold_ns
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the events as a JSON array of objects.
+pub fn write_json<W: Write>(mut out: W) -> io::Result<()> {
+    write!(out, "[")?;
+    for (i, e) in events().iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(
+            out,
+            "\n  {{\"thread\": {}, \"index\": {}, \"mode\": \"{}\", \"timestamp_ns\": {}, \
+             \"spin_ns\": {}, \"hold_ns\": {}}}",
+            e.thread,
+            e.index,
+            mode_name(e.mode),
+            e.timestamp_ns,
+            e.spin_ns,
+            e.hold_ns
+        )?;
+    }
+    writeln!(out, "\n]")
+}
+
+fn mode_name(mode: LockMode) -> &'static str {
+    match mode {
+        LockMode::Mutex => "mutex",
+        LockMode::Read => "read",
+        LockMode::Write => "write",
+    }
+}
+
+fn mode_code(mode: LockMode) -> u64 {
+    match mode {
+        LockMode::Mutex => 0,
+        LockMode::Read => 1,
+        LockMode::Write => 2,
+    }
+}
+
+fn mode_from_code(code: u64) -> LockMode {
+    match code {
+        0 => LockMode::Mutex,
+        1 => LockMode::Read,
+        _ => LockMode::Write,
+    }
+}
+
+/// An event slot, guarded by a sequence number that is odd while its owner
+/// writes to it.
+#[derive(Default)]
+struct Slot {
+    seq: AtomicUsize,
+    thread: AtomicU64,
+    index: AtomicUsize,
+    mode: AtomicU64,
+    timestamp_ns: AtomicU64,
+    spin_ns: AtomicU64,
+    hold_ns: AtomicU64,
+}
+
+/// The events of the threads that owned it, one at a time. Only the owning
+/// thread writes.
+struct Ring {
+    slots: Box<[Slot]>,
+    /// Events ever written
+    written: AtomicUsize,
+    /// Value of `written` at the last `clear`
+    cleared: AtomicUsize,
+    /// Whether the ring has no owner, as its thread exited
+    free: AtomicBool,
+    next: *mut Ring,
+}
+
+impl Ring {
+    /// Take over a free ring of the current capacity, or else allocate one
+    /// and add it to `RINGS`.
+    fn register() -> &'static Ring {
+        let capacity = capacity();
+        let mut ring = RINGS.load(Ordering::Acquire);
+        while !ring.is_null() {
+            let r = unsafe { &*ring };
+            // Acquire the writes of the previous owner, which we continue
+            if r.slots.len() == capacity
+                && r.free
+                    .compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return r;
+            }
+            ring = r.next;
+        }
+
+        let ring = Box::leak(Box::new(Ring {
+            slots: (0..capacity).map(|_| Slot::default()).collect(),
+            written: AtomicUsize::new(0),
+            cleared: AtomicUsize::new(0),
+            free: AtomicBool::new(false),
+            next: ptr::null_mut(),
+        }));
+        let mut head = RINGS.load(Ordering::Relaxed);
+        loop {
+            ring.next = head;
+            match RINGS.compare_exchange_weak(head, ring, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return ring,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    fn push(&self, e: &TraceEvent) {
+        let written = self.written.load(Ordering::Relaxed);
+        let slot = &self.slots[written % self.slots.len()];
+
+        let seq = slot.seq.load(Ordering::Relaxed);
+        slot.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.thread.store(e.thread, Ordering::Relaxed);
+        slot.index.store(e.index, Ordering::Relaxed);
+        slot.mode.store(mode_code(e.mode), Ordering::Relaxed);
+        slot.timestamp_ns.store(e.timestamp_ns, Ordering::Relaxed);
+        slot.spin_ns.store(e.spin_ns, Ordering::Relaxed);
+        slot.hold_ns.store(e.hold_ns, Ordering::Relaxed);
+        slot.seq.store(seq + 2, Ordering::Release);
+
+        self.written.store(written + 1, Ordering::Release);
+    }
+
+    fn collect(&self, events: &mut Vec<TraceEvent>) {
+        let written = self.written.load(Ordering::Acquire);
+        let cleared = self.cleared.load(Ordering::Acquire);
+        let first = written
+            .saturating_sub(self.slots.len())
+            .max(cleared.min(written));
+        for i in first..written {
+            let slot = &self.slots[i % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            if seq % 2 == 1 {
+                continue;
+            }
+            let event = TraceEvent {
+                thread: slot.thread.load(Ordering::Relaxed),
+                index: slot.index.load(Ordering::Relaxed),
+                mode: mode_from_code(slot.mode.load(Ordering::Relaxed)),
+                timestamp_ns: slot.timestamp_ns.load(Ordering::Relaxed),
+                spin_ns: slot.spin_ns.load(Ordering::Relaxed),
+                hold_ns: slot.hold_ns.load(Ordering::Relaxed),
+            };
+            fence(Ordering::Acquire);
+            if slot.seq.load(Ordering::Relaxed) == seq {
+                events.push(event);
+            }
+        }
+    }
+}
+
+/// A lock the current thread holds, waiting for its release to be recorded.
+struct Held {
+    index: usize,
+    mode: LockMode,
+    timestamp_ns: u64,
+    spin_ns: u64,
+    acquired: Instant,
+}
+
+struct Local {
+    thread: u64,
+    ring: &'static Ring,
+    held: Vec<Held>,
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        // Hand the ring, with our events in it, to the next new thread
+        self.ring.free.store(true, Ordering::Release);
+    }
+}
+
+thread_local! {
+    static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+/// When a thread started waiting for a lock.
+pub(crate) struct Start {
+    timestamp_ns: u64,
+    at: Instant,
+}
+
+impl Start {
+    pub(crate) fn now() -> Start {
+        let timestamp_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Start {
+            timestamp_ns,
+            at: Instant::now(),
+        }
+    }
+}
+
+/// Note that the current thread acquired lock `index`, having started to wait
+/// for it at `start`.
+pub(crate) fn acquired(index: usize, mode: LockMode, start: Start) {
+    let acquired = Instant::now();
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        let local = local.get_or_insert_with(|| Local {
+            thread: NEXT_THREAD.fetch_add(1, Ordering::Relaxed),
+            ring: Ring::register(),
+            held: Vec::new(),
+        });
+        local.held.push(Held {
+            index,
+            mode,
+            timestamp_ns: start.timestamp_ns,
+            spin_ns: (acquired - start.at).as_nanos() as u64,
+            acquired,
+        });
+    });
+}
+
+/// Record the acquisition of lock `index` that the current thread released
+/// at `released`.
+pub(crate) fn released(index: usize, mode: LockMode, released: Instant) {
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        let local = match local.as_mut() {
+            Some(local) => local,
+            None => return,
+        };
+        let i = match local
+            .held
+            .iter()
+            .rposition(|h| h.index == index && h.mode == mode)
+        {
+            Some(i) => i,
+            None => return,
+        };
+        let held = local.held.remove(i);
+        local.ring.push(&TraceEvent {
+            thread: local.thread,
+            index,
+            mode,
+            timestamp_ns: held.timestamp_ns,
+            spin_ns: held.spin_ns,
+            hold_ns: (released - held.acquired).as_nanos() as u64,
+        });
+    });
+}
@@ -0,0 +1,147 @@
+//! Transactions without the forked compiler.
+//!
+//! With the `transaction { }` keyword, the compiler works out which
+//! conflict-set locks each transaction needs. On a normal toolchain, declare
+//! them by hand in a [`TxSet`] and run the transaction with [`atomically`] or
+//! [`tx_block!`](crate::tx_block). Both take the locks through the same
+//! runtime as the lang items, so library and compiler transactions exclude
+//! each other when they use the same lock indices.
+
+use crate::{transaction_lock_set, transaction_unlock_set, LockMode, LockReq, TxToken};
+use std::iter::FromIterator;
+
+/// The conflict-set locks of a transaction, in canonical order.
+///
+/// Requests may be added in any order. A lock requested for reading and
+/// writing is written.
+///
+/// ```
+/// use txcell::TxSet;
+///
+/// let set = TxSet::new().write(3).read(1).read(3);
+/// assert_eq!(set.locks(), &[txcell::LockReq::read(1), txcell::LockReq::write(3)]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxSet {
+    reqs: Vec<LockReq>,
+}
+
+impl TxSet {
+    /// An empty set.
+    pub fn new() -> TxSet {
+        TxSet { reqs: Vec::new() }
+    }
+
+    /// Add the exclusive lock of conflict set `index`.
+    pub fn mutex(self, index: usize) -> TxSet {
+        self.with(LockReq::mutex(index))
+    }
+
+    /// Add the lock of conflict set `index`, for reading.
+    pub fn read(self, index: usize) -> TxSet {
+        self.with(LockReq::read(index))
+    }
+
+    /// Add the lock of conflict set `index`, for writing.
+    pub fn write(self, index: usize) -> TxSet {
+        self.with(LockReq::write(index))
+    }
+
+    /// Add `req`.
+    ///
+    /// # Panics
+    ///
+    /// If the set has the exclusive lock of the same conflict set and `req`
+    /// is for reading or writing, or the other way round. A conflict set is
+    /// protected by one kind of lock.
+    pub fn with(mut self, req: LockReq) -> TxSet {
+        match self.reqs.binary_search_by_key(&req.index, |r| r.index) {
+            Ok(i) => {
+                let held = &mut self.reqs[i];
+                assert!(
+                    (held.mode == LockMode::Mutex) == (req.mode == LockMode::Mutex),
+                    "txcell: conflict set {} requested as {:?} and {:?}",
+                    req.index,
+                    held.mode,
+                    req.mode
+                );
+                if req.mode == LockMode::Write {
+                    held.mode = LockMode::Write;
+                }
+            }
+            Err(i) => self.reqs.insert(i, req),
+        }
+        self
+    }
+
+    /// The requested locks, sorted by index.
+    pub fn locks(&self) -> &[LockReq] {
+        &self.reqs
+    }
+}
+
+impl FromIterator<LockReq> for TxSet {
+    fn from_iter<I: IntoIterator<Item = LockReq>>(reqs: I) -> TxSet {
+        reqs.into_iter().fold(TxSet::new(), TxSet::with)
+    }
+}
+
+impl From<&[LockReq]> for TxSet {
+    fn from(reqs: &[LockReq]) -> TxSet {
+        reqs.iter().copied().collect()
+    }
+}
+
+/// Releases the locks of a transaction, also if its closure panics.
+struct Locked<'a>(&'a TxSet);
+
+impl Drop for Locked<'_> {
+    fn drop(&mut self) {
+        transaction_unlock_set(self.0.locks());
+    }
+}
+
+/// Run `f` as a transaction holding the locks of `set`.
+///
+/// Nested calls are reentrant, like nested `transaction { }` blocks. `f`
+/// gets a token for checked borrows of `TxPtr`s.
+///
+/// ```
+/// use txcell::{atomically, TxPtr, TxSet};
+///
+/// // Conflict set 0 protects `a`
+/// let a = TxPtr::new(1);
+/// let set = TxSet::new().write(0);
+///
+/// atomically(&set, |tx| *a.borrow_mut_in(tx) += 1);
+/// assert_eq!(atomically(&TxSet::new().read(0), |tx| *a.borrow_in(tx)), 2);
+/// ```
+pub fn atomically<R, F: FnOnce(&TxToken) -> R>(set: &TxSet, f: F) -> R {
+    transaction_lock_set(set.locks());
+    let _locked = Locked(set);
+    TxToken::with_current(f)
+}
+
+/// Run a block as a transaction on the listed conflict-set locks.
+///
+/// Each lock is `mutex(index)`, `read(index)` or `write(index)`. The block is
+/// the body of a closure, so `return` leaves the transaction, not the
+/// enclosing function.
+///
+/// ```
+/// use txcell::{tx_block, TxPtr};
+///
+/// let a = TxPtr::new(1);
+/// let b = TxPtr::new(0);
+///
+/// tx_block!(read(0), write(1) => {
+///     *b.borrow_mut() = *a.borrow() + 1;
+/// });
+/// assert_eq!(tx_block!(read(1) => { *b.borrow() }), 2);
+/// ```
+#[macro_export]
+macro_rules! tx_block {
+    ($($mode:ident($index:expr)),+ => $body:block) => {
+        $crate::atomically(&$crate::TxSet::new()$(.$mode($index))+, |_| $body)
+    };
+}
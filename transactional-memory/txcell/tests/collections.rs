@@ -0,0 +1,109 @@
+//! Tests for the bounded transactional collections.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use txcell::{atomically, ConflictGroup, TxHashMap, TxQueue, TxSet, TxVec};
+
+const QUEUE: ConflictGroup = ConflictGroup::read_write(10);
+const COUNT: usize = 1000;
+
+/// A producer and a consumer sharing a queue smaller than what they pass.
+#[test]
+fn queue_between_threads() {
+    let queue = Arc::new(TxQueue::new_in(QUEUE, 8));
+    let set = TxSet::new().write(QUEUE.index());
+
+    let producer_queue = Arc::clone(&queue);
+    let producer_set = set.clone();
+    let producer = thread::spawn(move || {
+        let mut next = 0;
+        while next < COUNT {
+            if atomically(&producer_set, |tx| producer_queue.push(tx, next).is_ok()) {
+                next += 1;
+            }
+        }
+    });
+
+    let mut received = Vec::new();
+    while received.len() < COUNT {
+        if let Some(value) = atomically(&set, |tx| queue.pop(tx)) {
+            received.push(value);
+        }
+    }
+    producer.join().unwrap();
+
+    assert_eq!(received, (0..COUNT).collect::<Vec<_>>());
+    assert!(atomically(&set, |tx| queue.is_empty(tx)));
+}
+
+#[test]
+fn queue_bounded() {
+    let queue = TxQueue::new(2);
+    atomically(&TxSet::new().write(11), |tx| {
+        assert_eq!(queue.push(tx, 'a'), Ok(()));
+        assert_eq!(queue.push(tx, 'b'), Ok(()));
+        assert!(queue.is_full(tx));
+        assert_eq!(queue.push(tx, 'c'), Err('c'));
+        assert_eq!(queue.peek(tx), Some('a'));
+        assert_eq!(queue.pop(tx), Some('a'));
+        assert_eq!(queue.push(tx, 'c'), Ok(()));
+        assert_eq!((queue.len(tx), queue.capacity()), (2, 2));
+    });
+}
+
+#[test]
+fn vec_operations() {
+    let v = TxVec::new(4);
+    atomically(&TxSet::new().write(11), |tx| {
+        for i in 0..4 {
+            v.push(tx, i).unwrap();
+        }
+        assert_eq!(v.push(tx, 4), Err(4));
+        assert_eq!(v.insert(tx, 0, 4), Err(4));
+        assert_eq!(v.remove(tx, 1), 1);
+        v.insert(tx, 0, 5).unwrap();
+        assert_eq!(v.set(tx, 3, 6), 3);
+        assert_eq!(v.swap_remove(tx, 0), 5);
+        assert_eq!(v.pop(tx), Some(2));
+        assert_eq!(
+            (v.get(tx, 0), v.get(tx, 1), v.get(tx, 2)),
+            (Some(6), Some(0), None)
+        );
+        assert_eq!(v.len(tx), 2);
+    });
+}
+
+/// Random inserts and removes on a small map, checked against `HashMap`.
+#[test]
+fn map_matches_hashmap() {
+    let map = TxHashMap::new(32);
+    let mut model = HashMap::new();
+    let mut seed: u64 = 1;
+    let mut random = move || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize
+    };
+
+    atomically(&TxSet::new().write(11), |tx| {
+        assert_eq!(map.slots(tx), 64);
+        for _ in 0..10_000 {
+            let key = random() % 48;
+            if random() % 3 == 0 {
+                assert_eq!(map.remove(tx, &key), model.remove(&key));
+            } else {
+                let value = random();
+                match map.insert(tx, key, value) {
+                    Ok(old) => assert_eq!(old, model.insert(key, value)),
+                    Err(_) => assert!(model.len() == 32 && !model.contains_key(&key)),
+                }
+            }
+            assert_eq!(map.len(tx), model.len());
+        }
+        for key in 0..48 {
+            assert_eq!(map.get(tx, &key), model.get(&key).copied());
+            assert_eq!(map.contains_key(tx, &key), model.contains_key(&key));
+        }
+    });
+}
@@ -101,6 +101,26 @@ fn different_transactions() {
     assert_eq!(*a_saved.borrow(), 0);
 }
 
+/// A transaction calls a function that contains a transaction on the same
+/// TxPtr. The nested transaction takes the lock the outer one holds already.
+#[test]
+fn nested_function_call() {
+    let a = Arc::new(TxPtr::new(0));
+    let a_clone = a.clone();
+    let a_saved = a.clone();
+
+    let t1 = thread::spawn(move || {
+        transaction {
+            *a.borrow_mut() += 1;
+            increment_with_tx(a.clone());
+        }
+    });
+    let t2 = thread::spawn(move || increment_with_tx(a_clone));
+    let _ = t1.join();
+    let _ = t2.join();
+    assert_eq!(*a_saved.borrow(), 3);
+}
+
 fn increment(a: Arc<TxPtr<i32>>) {
     let rc_ref = a.borrow_mut();
     *rc_ref += 1;
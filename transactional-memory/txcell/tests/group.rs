@@ -0,0 +1,68 @@
+//! Conflict groups declared with `new_in`.
+use txcell::{atomically, ConflictGroup, TxCell, TxPtr, TxSet};
+
+const ACCOUNTS: ConflictGroup = ConflictGroup::read_write(16);
+const LOG: ConflictGroup = ConflictGroup::mutex(17);
+
+#[test]
+fn covered_accesses() {
+    let balance = TxPtr::new_in(ACCOUNTS, 100);
+    let entries = TxCell::new_in(LOG, 0);
+    assert_eq!(balance.group(), Some(ACCOUNTS));
+    assert_eq!(TxPtr::new(0).group(), None);
+
+    atomically(&TxSet::new().write(16).mutex(17), |tx| {
+        *balance.borrow_mut_in(tx) -= 10;
+        entries.set_in(tx, entries.get_in(tx) + 1);
+    });
+    let (balance, entries) = atomically(&TxSet::new().read(16).mutex(17), |tx| {
+        (*balance.borrow_in(tx), entries.get_in(tx))
+    });
+    assert_eq!((balance, entries), (90, 1));
+}
+
+#[test]
+fn ungrouped_not_checked() {
+    let a = TxPtr::new(1);
+    atomically(&TxSet::new().read(16), |tx| *a.borrow_mut_in(tx) += 1);
+    atomically(&TxSet::new().read(16), |tx| assert_eq!(*a.borrow_in(tx), 2));
+}
+
+#[test]
+#[should_panic(expected = "in conflict group 16 is read without holding lock 16")]
+fn read_without_lock() {
+    let balance = TxPtr::new_in(ACCOUNTS, 100);
+    atomically(&TxSet::new().write(17), |tx| {
+        balance.borrow_in(tx);
+    });
+}
+
+#[test]
+#[should_panic(expected = "is written without holding lock 16 for writing")]
+fn write_under_read_lock() {
+    let balance = TxPtr::new_in(ACCOUNTS, 100);
+    atomically(&TxSet::new().read(16), |tx| *balance.borrow_mut_in(tx) += 1);
+}
+
+#[test]
+#[should_panic(expected = "TxCell at")]
+fn cell_without_lock() {
+    let entries = TxCell::new_in(LOG, 0);
+    atomically(&TxSet::new().mutex(16), |tx| entries.set_in(tx, 1));
+}
+
+/// The exclusive and the read/write lock of the same index are different
+/// locks, so neither covers a group of the other table.
+#[test]
+#[should_panic(expected = "is read without holding mutex 17")]
+fn mutex_group_under_write_lock() {
+    let entries = TxCell::new_in(LOG, 0);
+    atomically(&TxSet::new().write(17), |tx| entries.get_in(tx));
+}
+
+#[test]
+#[should_panic(expected = "is written without holding lock 16 for writing")]
+fn read_write_group_under_mutex() {
+    let balance = TxPtr::new_in(ACCOUNTS, 100);
+    atomically(&TxSet::new().mutex(16), |tx| *balance.borrow_mut_in(tx) += 1);
+}
@@ -0,0 +1,112 @@
+//! Transactions through `atomically` and `tx_block!`, which run on a normal
+//! toolchain. Conflict-set indices are assigned by hand.
+use std::collections::VecDeque;
+use std::panic;
+use std::sync::Arc;
+use std::thread;
+use txcell::{atomically, tx_block, LockReq, TxPtr, TxSet};
+
+#[test]
+fn tx_set_order() {
+    let set = TxSet::new().write(4).read(2).mutex(7).read(4);
+    assert_eq!(
+        set.locks(),
+        &[LockReq::read(2), LockReq::write(4), LockReq::mutex(7)]
+    );
+    assert_eq!(TxSet::from(&[LockReq::read(4), LockReq::write(2)][..]).locks()[0].index, 2);
+}
+
+#[test]
+#[should_panic(expected = "requested as")]
+fn tx_set_mixed_kinds() {
+    let _ = TxSet::new().mutex(1).read(1);
+}
+
+/// The `arrays` test: one conflict set per cell.
+#[test]
+fn array() {
+    const N: usize = 3;
+    const X: usize = 300;
+
+    let cells: Vec<_> = (0..N).map(|_| Arc::new(TxPtr::new(0))).collect();
+
+    let mut threads = vec![];
+    for i in 0..N {
+        for _ in 0..X {
+            let cell = Arc::clone(&cells[i]);
+            threads.push(thread::spawn(move || {
+                atomically(&TxSet::new().mutex(i), |_tx| *cell.borrow_mut() += 1);
+            }));
+        }
+    }
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let sum: usize = (0..N)
+        .map(|i| atomically(&TxSet::new().mutex(i), |_tx| *cells[i].borrow()))
+        .sum();
+    assert_eq!(sum, X * N);
+}
+
+/// The `queues` test: a mover takes from one queue to another.
+#[test]
+fn two_queues() {
+    const COUNT: usize = 1000;
+    const QOUT: usize = 10;
+    const QIN: usize = 11;
+
+    let qout = Arc::new(TxPtr::new(VecDeque::with_capacity(COUNT)));
+    let qin = Arc::new(TxPtr::new(VecDeque::with_capacity(COUNT)));
+
+    let producer = {
+        let qout = Arc::clone(&qout);
+        thread::spawn(move || {
+            for i in 0..COUNT {
+                tx_block!(write(QOUT) => { qout.borrow_mut().push_back(i) });
+            }
+        })
+    };
+    let mover = {
+        let (qout, qin) = (Arc::clone(&qout), Arc::clone(&qin));
+        thread::spawn(move || {
+            let mut moved = 0;
+            while moved < COUNT {
+                tx_block!(write(QOUT), write(QIN) => {
+                    if let Some(x) = qout.borrow_mut().pop_front() {
+                        qin.borrow_mut().push_back(x);
+                        moved += 1;
+                    }
+                });
+            }
+        })
+    };
+    producer.join().unwrap();
+    mover.join().unwrap();
+
+    let items: Vec<_> = tx_block!(read(QIN) => { qin.borrow().iter().copied().collect() });
+    assert_eq!(items, (0..COUNT).collect::<Vec<_>>());
+}
+
+#[test]
+fn nested_atomically() {
+    let a = TxPtr::new(0);
+    let set = TxSet::new().mutex(12);
+    atomically(&set, |_tx| {
+        *a.borrow_mut() += 1;
+        atomically(&set, |_tx| *a.borrow_mut() += 1);
+    });
+    assert_eq!(atomically(&set, |_tx| *a.borrow()), 2);
+}
+
+#[test]
+fn panic_releases_locks() {
+    let set = TxSet::new().write(13);
+    let result = panic::catch_unwind(|| atomically(&set, |_tx| panic!("abort")));
+    assert!(result.is_err());
+
+    // Another thread can take the lock again
+    thread::spawn(move || atomically(&set, |tx| assert_eq!(tx.held_locks().len(), 1)))
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,65 @@
+//! Tests for taking several conflict-set locks with one call.
+use std::sync::Arc;
+use std::thread;
+use txcell::{transaction_lock_set, transaction_unlock_set, LockReq, TxPtr};
+
+#[test]
+fn lock_set() {
+    const N: usize = 4;
+    const X: usize = 1000;
+
+    let a = Arc::new(TxPtr::new(0));
+    let b = Arc::new(TxPtr::new(0));
+
+    let mut threads = vec![];
+    for i in 0..N {
+        let (a, b) = (Arc::clone(&a), Arc::clone(&b));
+        threads.push(thread::spawn(move || {
+            let reqs = if i % 2 == 0 {
+                [LockReq::write(0), LockReq::write(1)]
+            } else {
+                [LockReq::mutex(0), LockReq::write(1)]
+            };
+            for _ in 0..X {
+                transaction_lock_set(&reqs);
+                if i % 2 == 0 {
+                    *a.borrow_mut() += 1;
+                }
+                *b.borrow_mut() += 1;
+                transaction_unlock_set(&reqs);
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+    let reqs = [LockReq::read(0), LockReq::read(1)];
+    transaction_lock_set(&reqs);
+    assert_eq!(*a.borrow(), X * N / 2);
+    assert_eq!(*b.borrow(), X * N);
+    transaction_unlock_set(&reqs);
+}
+
+#[test]
+fn readers_share_a_set() {
+    let reqs = [LockReq::read(2), LockReq::read(3)];
+    transaction_lock_set(&reqs);
+    transaction_lock_set(&reqs);
+    transaction_unlock_set(&reqs);
+    transaction_unlock_set(&reqs);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "sorted by index")]
+fn unsorted_set() {
+    transaction_lock_set(&[LockReq::write(5), LockReq::write(4)]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "without duplicates")]
+fn duplicate_set() {
+    transaction_lock_set(&[LockReq::read(6), LockReq::write(6)]);
+}
@@ -0,0 +1,84 @@
+//! Nested transactions taking the same conflict-set locks again.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use txcell::{
+    lock_mutex, read_lock_mutex, read_unlock_mutex, unlock_mutex, write_lock_mutex,
+    write_unlock_mutex, TxPtr,
+};
+
+fn add_one(a: &TxPtr<usize>) {
+    lock_mutex(0);
+    *a.borrow_mut() += 1;
+    unlock_mutex(0);
+}
+
+/// Start a reader of lock `n` on another thread and report whether it got
+/// through within a few milliseconds.
+fn reader_gets_in(n: usize) -> (bool, JoinHandle<()>) {
+    let done = Arc::new(AtomicBool::new(false));
+    let reader_done = Arc::clone(&done);
+    let reader = thread::spawn(move || {
+        read_lock_mutex(n);
+        read_unlock_mutex(n);
+        reader_done.store(true, Ordering::SeqCst);
+    });
+    thread::sleep(Duration::from_millis(10));
+    (done.load(Ordering::SeqCst), reader)
+}
+
+#[test]
+fn nested_mutex() {
+    let a = Arc::new(TxPtr::new(0));
+
+    let threads: Vec<_> = (0..2)
+        .map(|_| {
+            let a = Arc::clone(&a);
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    lock_mutex(0);
+                    add_one(&a);
+                    add_one(&a);
+                    unlock_mutex(0);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    lock_mutex(0);
+    assert_eq!(*a.borrow(), 400);
+    unlock_mutex(0);
+}
+
+#[test]
+fn nested_read_in_write() {
+    write_lock_mutex(1);
+    read_lock_mutex(1);
+    read_unlock_mutex(1);
+
+    // Still held for writing
+    let (got_in, reader) = reader_gets_in(1);
+    assert!(!got_in);
+
+    write_unlock_mutex(1);
+    reader.join().unwrap();
+}
+
+/// Upgrading would release the read lock out of the lock order, so a nested
+/// write under a read is rejected.
+#[test]
+#[should_panic(expected = "holds for reading")]
+fn nested_write_in_read() {
+    read_lock_mutex(2);
+    write_lock_mutex(2);
+}
+
+#[test]
+#[should_panic(expected = "does not hold")]
+fn unlock_not_held() {
+    unlock_mutex(3);
+}
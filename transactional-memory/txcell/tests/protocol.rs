@@ -0,0 +1,54 @@
+//! Running transactions on a registered lock protocol.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use txcell::{
+    declare_locks_with, lock_mutex, read_lock_mutex, read_unlock_mutex, unlock_mutex,
+    write_lock_mutex, write_unlock_mutex, TicketLock, TxLockProtocol,
+};
+
+static LOCKS: AtomicUsize = AtomicUsize::new(0);
+static READ_LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+/// A ticket lock that counts its acquisitions.
+struct Counting(TicketLock);
+
+impl TxLockProtocol for Counting {
+    fn new() -> Self {
+        Counting(TicketLock::new())
+    }
+
+    fn lock(&self) {
+        LOCKS.fetch_add(1, Ordering::Relaxed);
+        self.0.lock()
+    }
+
+    unsafe fn unlock(&self) {
+        self.0.unlock()
+    }
+
+    fn read_lock(&self) {
+        READ_LOCKS.fetch_add(1, Ordering::Relaxed);
+        self.0.read_lock()
+    }
+
+    unsafe fn read_unlock(&self) {
+        self.0.read_unlock()
+    }
+}
+
+#[test]
+fn registered_protocol() {
+    declare_locks_with::<Counting>(4);
+    // The protocol cannot be replaced once the tables exist, even with the
+    // same number of locks
+    assert!(std::panic::catch_unwind(|| declare_locks_with::<TicketLock>(4)).is_err());
+
+    lock_mutex(0);
+    unlock_mutex(0);
+    write_lock_mutex(1);
+    write_unlock_mutex(1);
+    read_lock_mutex(2);
+    read_unlock_mutex(2);
+
+    assert_eq!(LOCKS.load(Ordering::Relaxed), 2);
+    assert_eq!(READ_LOCKS.load(Ordering::Relaxed), 1);
+}
@@ -0,0 +1,30 @@
+//! Tests for the conflict-set lock tables behind the transaction lang items.
+use txcell::{declare_locks, lock_mutex, read_lock_mutex, read_unlock_mutex, unlock_mutex};
+
+/// Every test declares the same count, so they may run in any order.
+const LOCKS: usize = 64;
+
+#[test]
+fn declared_locks() {
+    declare_locks(LOCKS);
+    for n in 0..LOCKS {
+        lock_mutex(n);
+        unlock_mutex(n);
+        read_lock_mutex(n);
+        read_unlock_mutex(n);
+    }
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn index_out_of_range() {
+    declare_locks(LOCKS);
+    lock_mutex(LOCKS);
+}
+
+#[test]
+#[should_panic(expected = "already allocated")]
+fn redeclare_with_other_count() {
+    declare_locks(LOCKS);
+    declare_locks(LOCKS + 1);
+}
@@ -0,0 +1,63 @@
+//! Checked borrows of `TxPtr`s through transaction tokens.
+use txcell::{atomically, write_lock_mutex, write_unlock_mutex, TxPtr, TxSet, TxToken};
+
+#[test]
+fn checked_borrows() {
+    let a = TxPtr::new(1);
+    let set = TxSet::new().write(14);
+
+    atomically(&set, |tx| {
+        *a.borrow_mut_in(tx) += 1;
+        let first = a.borrow_in(tx);
+        let second = a.borrow_in(tx);
+        assert_eq!(*first + *second, 4);
+    });
+    // A new transaction starts without borrows
+    atomically(&set, |tx| *a.borrow_mut_in(tx) += 1);
+    atomically(&set, |tx| assert_eq!(*a.borrow_in(tx), 3));
+}
+
+#[test]
+fn held_locks() {
+    let set = TxSet::new().read(15).write(14);
+    atomically(&set, |tx| assert_eq!(tx.held_locks(), set.locks()));
+}
+
+#[test]
+#[should_panic(expected = "outside of a transaction")]
+fn token_outside_transaction() {
+    TxToken::with_current(|_| ());
+}
+
+#[test]
+#[should_panic(expected = "already mutably borrowed")]
+fn second_mutable_borrow() {
+    let a = TxPtr::new(0);
+    atomically(&TxSet::new().write(14), |tx| {
+        let _first = a.borrow_mut_in(tx);
+        let _second = a.borrow_mut_in(tx);
+    });
+}
+
+#[test]
+#[should_panic(expected = "cannot be borrowed mutably")]
+fn mutable_borrow_while_shared() {
+    let a = TxPtr::new(0);
+    atomically(&TxSet::new().write(14), |tx| {
+        let _shared = a.borrow_in(tx);
+        let _mutable = a.borrow_mut_in(tx);
+    });
+}
+
+#[test]
+#[should_panic(expected = "transaction that has ended")]
+fn token_outlives_transaction() {
+    let a = TxPtr::new(0);
+    let set = TxSet::new().write(14);
+    atomically(&set, |tx| {
+        // Releasing the lock by hand ends the transaction of `tx`
+        write_unlock_mutex(14);
+        write_lock_mutex(14);
+        a.borrow_in(tx);
+    });
+}
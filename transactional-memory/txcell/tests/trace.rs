@@ -0,0 +1,75 @@
+//! Tests for the lock traces of the `trace` feature.
+#![cfg(feature = "trace")]
+use std::thread;
+use txcell::trace::{self, TraceEvent};
+use txcell::{lock_mutex, read_lock_mutex, read_unlock_mutex, unlock_mutex, LockMode};
+
+fn events_of(thread: u64) -> Vec<TraceEvent> {
+    trace::events()
+        .into_iter()
+        .filter(|e| e.thread == thread)
+        .collect()
+}
+
+/// Take a few locks on a new thread and return its trace thread id.
+fn run(f: fn()) -> u64 {
+    thread::spawn(move || {
+        f();
+        trace::thread_id().unwrap()
+    })
+    .join()
+    .unwrap()
+}
+
+#[test]
+fn records_locks() {
+    let thread = run(|| {
+        lock_mutex(0);
+        read_lock_mutex(1);
+        read_unlock_mutex(1);
+        unlock_mutex(0);
+    });
+
+    let events = events_of(thread);
+    assert_eq!(events.len(), 2);
+    assert_eq!((events[0].index, events[0].mode), (0, LockMode::Mutex));
+    assert_eq!((events[1].index, events[1].mode), (1, LockMode::Read));
+    assert!(events[0].timestamp_ns <= events[1].timestamp_ns);
+    assert!(events[0].hold_ns >= events[1].hold_ns);
+}
+
+#[test]
+fn ring_keeps_newest_events() {
+    let thread = run(|| {
+        for _ in 0..trace::capacity() + 10 {
+            lock_mutex(2);
+            unlock_mutex(2);
+        }
+        lock_mutex(3);
+        unlock_mutex(3);
+    });
+
+    let events = events_of(thread);
+    assert_eq!(events.len(), trace::capacity());
+    assert_eq!(events.last().unwrap().index, 3);
+}
+
+#[test]
+fn dumps() {
+    run(|| {
+        lock_mutex(4);
+        unlock_mutex(4);
+    });
+
+    let mut csv = Vec::new();
+    trace::write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(csv.starts_with("thread,index,mode,timestamp_ns,spin_ns,hold_ns\n"));
+    assert!(csv.lines().skip(1).any(|line| line.split(',').nth(1) == Some("4")));
+
+    let mut json = Vec::new();
+    trace::write_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.starts_with('['));
+    assert!(json.contains("\"index\": 4, \"mode\": \"mutex\""));
+}
@@ -0,0 +1,36 @@
+//! Reuse of the trace rings of exited threads. A file of its own, so that no
+//! other test thread takes over the ring first.
+#![cfg(feature = "trace")]
+use std::thread;
+use txcell::trace;
+use txcell::{lock_mutex, unlock_mutex};
+
+/// Take lock `index` `times` times on a new thread and return its trace
+/// thread id.
+fn run(index: usize, times: usize) -> u64 {
+    thread::spawn(move || {
+        for _ in 0..times {
+            lock_mutex(index);
+            unlock_mutex(index);
+        }
+        trace::thread_id().unwrap()
+    })
+    .join()
+    .unwrap()
+}
+
+#[test]
+fn exited_thread_hands_on_its_ring() {
+    let first = run(0, 1);
+    let events = trace::events();
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].thread, events[0].index), (first, 0));
+
+    // The next thread writes into the same ring, so a full ring of its
+    // events replaces those of the first thread
+    let second = run(1, trace::capacity());
+    assert_ne!(first, second);
+    let events = trace::events();
+    assert_eq!(events.len(), trace::capacity());
+    assert!(events.iter().all(|e| e.thread == second && e.index == 1));
+}
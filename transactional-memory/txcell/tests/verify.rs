@@ -0,0 +1,68 @@
+//! Tests for the lock checks of the `verify` feature.
+#![cfg(feature = "verify")]
+use txcell::{
+    atomically, lock_mutex, read_lock_mutex, read_unlock_mutex, unlock_mutex, ConflictGroup,
+    TxCell, TxPtr, TxSet,
+};
+
+const GROUP: ConflictGroup = ConflictGroup::read_write(18);
+
+#[test]
+fn covered_accesses() {
+    let a = TxPtr::new_in(GROUP, 1);
+    let b = TxCell::new_in(ConflictGroup::mutex(19), 0);
+
+    atomically(&TxSet::new().write(18), |_| *a.borrow_mut() += 1);
+    read_lock_mutex(18);
+    lock_mutex(19);
+    b.set(*a.borrow() + 1);
+    assert_eq!(b.get(), 3);
+    unlock_mutex(19);
+    read_unlock_mutex(18);
+
+    // Bound to both locks at first, then to the one held at every access
+    let c = TxPtr::new(0);
+    atomically(&TxSet::new().write(18).mutex(19), |_| *c.borrow_mut() += 1);
+    atomically(&TxSet::new().mutex(19), |_| *c.borrow_mut() += 1);
+    atomically(&TxSet::new().read(18).mutex(19), |_| assert_eq!(*c.borrow(), 2));
+}
+
+/// A value without a group is bound to the lock of its first transaction.
+#[test]
+#[should_panic(expected = "allocated at tests/verify.rs:")]
+fn ungrouped_under_other_lock() {
+    let a = TxPtr::new(1);
+    *a.borrow_mut() += 1;
+    atomically(&TxSet::new().write(18), |_| *a.borrow_mut() += 1);
+    atomically(&TxSet::new().write(17), |_| *a.borrow_mut() += 1);
+}
+
+#[test]
+#[should_panic(expected = "is read without holding mutex 19, which it was first accessed under")]
+fn ungrouped_outside_transaction() {
+    let b = TxCell::new(0);
+    atomically(&TxSet::new().mutex(19), |_| b.set(1));
+    b.get();
+}
+
+#[test]
+#[should_panic(expected = "is read without holding lock 18; held locks: []")]
+fn read_outside_transaction() {
+    let a = TxPtr::new_in(GROUP, 1);
+    a.borrow();
+}
+
+#[test]
+#[should_panic(expected = "held locks: [LockReq { index: 18, mode: Read }]")]
+fn write_under_read_lock() {
+    let a = TxPtr::new_in(GROUP, 1);
+    // `atomically` releases the lock when the access panics
+    atomically(&TxSet::new().read(18), |_| *a.borrow_mut() += 1);
+}
+
+#[test]
+#[should_panic(expected = "TxCell at")]
+fn cell_with_other_lock() {
+    let b = TxCell::new_in(GROUP, 0);
+    atomically(&TxSet::new().mutex(19), |_| b.set(1));
+}